@@ -1 +1,41 @@
-fn main() {}
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use dynarule::{Condition, Outcome, Rule, RuleEngine};
+use std::collections::HashMap;
+
+fn build_engine() -> RuleEngine {
+    let rules = (0..50)
+        .map(|i| Rule {
+            condition: Condition::And(vec![
+                Condition::Simple(format!("age > {}", i)),
+                Condition::Simple("status = active".to_string()),
+            ]),
+            outcomes: vec![Outcome {
+                key: format!("rule_{}", i),
+                value: serde_json::json!("Hello, {{name}}!"),
+                ..Default::default()
+            }],
+            priority: i % 5,
+        })
+        .collect();
+    RuleEngine::new(rules)
+}
+
+fn bench_evaluate(c: &mut Criterion) {
+    let engine = build_engine();
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(30));
+    input.insert("status".to_string(), serde_json::json!("active"));
+    input.insert("name".to_string(), serde_json::json!("Haile"));
+    let context = HashMap::new();
+
+    c.bench_function("evaluate 50 rules", |b| {
+        b.iter(|| {
+            engine
+                .evaluate(black_box(&input), black_box(&context))
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_evaluate);
+criterion_main!(benches);
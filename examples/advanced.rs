@@ -5,10 +5,11 @@ use std::fs;
 fn main() {
     let initial_rules = vec![Rule {
         condition: Condition::Simple("age > 10".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("Welcome, {{name}} at {{time}}!"),
-        },
+            ..Default::default()
+        }],
         priority: 1,
     }];
     let mut engine = RuleEngine::new(initial_rules).with_function("length", |value| {
@@ -0,0 +1,628 @@
+use crate::error::RuleEngineError;
+use crate::number::Number;
+use crate::parser::{self, CidrSet, CompareOp, Expr, FieldPath, Pattern};
+use crate::types::{Condition, Outcome, OutcomeType, Rule};
+use crate::value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use tera::Tera;
+
+/// Rules compiled into an executable form: sorted by priority, with every
+/// simple condition parsed and every outcome template registered with Tera.
+pub(crate) struct CompiledRuleSet {
+    pub(crate) rules: Vec<CompiledRule>,
+    pub(crate) tera: Tera,
+}
+
+pub(crate) struct CompiledRule {
+    /// The position of the rule in the rule set as given, before sorting.
+    pub(crate) index: usize,
+    pub(crate) condition: CompiledCondition,
+    pub(crate) outcomes: Vec<CompiledOutcome>,
+}
+
+pub(crate) enum CompiledCondition {
+    Expr(Expr),
+    And(Vec<CompiledCondition>),
+    Or(Vec<CompiledCondition>),
+    Not(Box<CompiledCondition>),
+    Xor(Vec<CompiledCondition>),
+    AtLeast {
+        n: usize,
+        conditions: Vec<CompiledCondition>,
+    },
+    AtMost {
+        n: usize,
+        conditions: Vec<CompiledCondition>,
+    },
+    /// Evaluates `condition` against each element of `collection`, bound to `binding`.
+    Quantifier {
+        quantifier: Quantifier,
+        field: String,
+        collection: Expr,
+        binding: String,
+        condition: Box<CompiledCondition>,
+    },
+    /// A condition that failed to compile; evaluating it reports the error.
+    Invalid(RuleEngineError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Quantifier {
+    Any,
+    All,
+    None,
+}
+
+pub(crate) struct CompiledOutcome {
+    pub(crate) key: String,
+    pub(crate) value: CompiledValue,
+    pub(crate) value_type: Option<OutcomeType>,
+}
+
+pub(crate) enum CompiledValue {
+    Literal(serde_json::Value),
+    /// The name of a template registered with the rule set's Tera instance.
+    Template(String),
+    /// An array holding templates.
+    Array(Vec<CompiledValue>),
+    /// An object holding templates, as key and value pairs. Keys are strings or,
+    /// when keys are templated, templates.
+    Object(Vec<(CompiledValue, CompiledValue)>),
+    /// A value computed by an expression, as given by an outcome's `expr`.
+    Expr(Expr),
+    Invalid(RuleEngineError),
+}
+
+/// Engine settings that change how rules compile.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CompileOptions {
+    /// Compile object keys in outcome values as templates.
+    pub(crate) templated_keys: bool,
+    /// Compile bare identifiers on the right-hand side of comparisons as field
+    /// references rather than strings.
+    pub(crate) bareword_fields: bool,
+}
+
+impl CompiledRuleSet {
+    /// Compiles `rules`. Rules that fail to compile are kept and report their
+    /// error when evaluated; use [`CompiledRuleSet::validate`] to reject them upfront.
+    pub(crate) fn compile(rules: &[Rule], options: CompileOptions) -> Self {
+        let mut tera = template_engine();
+        let mut sorted: Vec<(usize, &Rule)> = rules.iter().enumerate().collect();
+        sorted.sort_by_key(|(_, rule)| std::cmp::Reverse(rule.priority));
+
+        let rules = sorted
+            .into_iter()
+            .map(|(index, rule)| CompiledRule {
+                index,
+                condition: compile_condition(&rule.condition, options),
+                outcomes: rule
+                    .outcomes
+                    .iter()
+                    .enumerate()
+                    .map(|(position, outcome)| CompiledOutcome {
+                        key: outcome.key.clone(),
+                        value: compile_outcome(
+                            outcome,
+                            &format!("rule{}.{}", index, position),
+                            options,
+                            &mut tera,
+                        ),
+                        value_type: outcome.value_type,
+                    })
+                    .collect(),
+            })
+            .collect();
+        CompiledRuleSet { rules, tera }
+    }
+
+    /// Returns the first compilation error in the rule set, if any.
+    pub(crate) fn validate(&self) -> Result<(), RuleEngineError> {
+        for rule in &self.rules {
+            validate_condition(&rule.condition)?;
+            for outcome in &rule.outcomes {
+                validate_value(&outcome.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compiles the value of an outcome, from its `expr` if it has one, naming its
+/// templates after `name`.
+fn template_engine() -> Tera {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![]);
+    tera.register_filter("pointer", pointer_filter);
+    tera
+}
+
+fn compile_outcome(
+    outcome: &Outcome,
+    name: &str,
+    options: CompileOptions,
+    tera: &mut Tera,
+) -> CompiledValue {
+    let invalid = |message: &str| {
+        CompiledValue::Invalid(RuleEngineError::ParseError(format!(
+            "Outcome '{}' {}",
+            outcome.key, message
+        )))
+    };
+    match &outcome.expr {
+        Some(_) if !outcome.value.is_null() => invalid("has both a value and an expr"),
+        Some(_) if outcome.value_type.is_some() => invalid("has both a type and an expr"),
+        Some(expr) => match compile_expr(expr, options) {
+            Ok(expr) => CompiledValue::Expr(expr),
+            Err(e) => CompiledValue::Invalid(e),
+        },
+        None => match compile_value(&outcome.value, name, options.templated_keys, tera) {
+            CompiledValue::Literal(_) if outcome.value_type.is_some() => {
+                invalid("has a type but its value holds no template")
+            }
+            value => value,
+        },
+    }
+}
+
+/// Parses an expression, folding constant subexpressions and checking the result.
+fn compile_expr(text: &str, options: CompileOptions) -> Result<Expr, RuleEngineError> {
+    let expr = fold_constants(parser::parse_expression(text)?, options);
+    check_expr(&expr)?;
+    Ok(expr)
+}
+
+fn compile_condition(condition: &Condition, options: CompileOptions) -> CompiledCondition {
+    match condition {
+        Condition::Simple(expr) => match compile_expr(expr, options) {
+            Ok(expr) => CompiledCondition::Expr(expr),
+            Err(e) => CompiledCondition::Invalid(e),
+        },
+        Condition::And(conditions) => CompiledCondition::And(
+            conditions
+                .iter()
+                .map(|condition| compile_condition(condition, options))
+                .collect(),
+        ),
+        Condition::Or(conditions) => CompiledCondition::Or(
+            conditions
+                .iter()
+                .map(|condition| compile_condition(condition, options))
+                .collect(),
+        ),
+        Condition::Not(condition) => {
+            CompiledCondition::Not(Box::new(compile_condition(condition, options)))
+        }
+        Condition::Xor(conditions) => CompiledCondition::Xor(
+            conditions
+                .iter()
+                .map(|condition| compile_condition(condition, options))
+                .collect(),
+        ),
+        Condition::AtLeast { n, conditions } => CompiledCondition::AtLeast {
+            n: *n,
+            conditions: conditions
+                .iter()
+                .map(|condition| compile_condition(condition, options))
+                .collect(),
+        },
+        Condition::AtMost { n, conditions } => CompiledCondition::AtMost {
+            n: *n,
+            conditions: conditions
+                .iter()
+                .map(|condition| compile_condition(condition, options))
+                .collect(),
+        },
+        Condition::Any {
+            field,
+            binding,
+            condition,
+        } => compile_quantifier(Quantifier::Any, field, binding, condition, options),
+        Condition::All {
+            field,
+            binding,
+            condition,
+        } => compile_quantifier(Quantifier::All, field, binding, condition, options),
+        Condition::None {
+            field,
+            binding,
+            condition,
+        } => compile_quantifier(Quantifier::None, field, binding, condition, options),
+        Condition::Ref(name) => CompiledCondition::Invalid(unresolved_reference(name)),
+    }
+}
+
+fn unresolved_reference(name: &str) -> RuleEngineError {
+    RuleEngineError::ParseError(format!(
+        "Unresolved condition reference '{}'; references are resolved by parser::parse_rules",
+        name
+    ))
+}
+
+fn compile_quantifier(
+    quantifier: Quantifier,
+    field: &str,
+    binding: &str,
+    condition: &Condition,
+    options: CompileOptions,
+) -> CompiledCondition {
+    match compile_expr(field, options) {
+        Ok(collection) => CompiledCondition::Quantifier {
+            quantifier,
+            field: field.to_string(),
+            collection,
+            binding: binding.to_string(),
+            condition: Box::new(compile_condition(condition, options)),
+        },
+        Err(e) => CompiledCondition::Invalid(e),
+    }
+}
+
+/// Folds lists made only of literals into literal arrays and resolves bare
+/// identifiers to strings or, with `bareword_fields`, fields.
+fn fold_constants(expr: Expr, options: CompileOptions) -> Expr {
+    match expr {
+        Expr::Compare { op, left, right } => Expr::Compare {
+            op,
+            left: Box::new(fold_constants(*left, options)),
+            right: Box::new(match (op, fold_constants(*right, options)) {
+                (
+                    CompareOp::Matches | CompareOp::NotMatches,
+                    Expr::Literal(serde_json::Value::String(pattern)),
+                ) => match Pattern::new(&pattern) {
+                    Ok(pattern) => Expr::Pattern(pattern),
+                    // Reported by `check_expr`.
+                    Err(_) => Expr::Literal(serde_json::Value::String(pattern)),
+                },
+                (_, right) => right,
+            }),
+        },
+        Expr::Call { name, args } => Expr::Call {
+            name,
+            args: args
+                .into_iter()
+                .map(|expr| fold_constants(expr, options))
+                .collect(),
+        },
+        Expr::List(items) => fold_list(
+            items
+                .into_iter()
+                .map(|expr| fold_constants(expr, options))
+                .collect(),
+        ),
+        Expr::Arithmetic { op, left, right } => Expr::Arithmetic {
+            op,
+            left: Box::new(fold_constants(*left, options)),
+            right: Box::new(fold_constants(*right, options)),
+        },
+        Expr::Negate(operand) => Expr::Negate(Box::new(fold_constants(*operand, options))),
+        Expr::Between {
+            value,
+            low,
+            high,
+            low_inclusive,
+            high_inclusive,
+        } => Expr::Between {
+            value: Box::new(fold_constants(*value, options)),
+            low: Box::new(fold_constants(*low, options)),
+            high: Box::new(fold_constants(*high, options)),
+            low_inclusive,
+            high_inclusive,
+        },
+        Expr::Coalesce { left, right } => Expr::Coalesce {
+            left: Box::new(fold_constants(*left, options)),
+            right: Box::new(fold_constants(*right, options)),
+        },
+        Expr::Check { op, operand } => Expr::Check {
+            op,
+            operand: Box::new(fold_constants(*operand, options)),
+        },
+        Expr::Bareword(word) if options.bareword_fields => Expr::Field(FieldPath::key(&word)),
+        Expr::Bareword(word) => Expr::Literal(serde_json::Value::String(word)),
+        other => other,
+    }
+}
+
+/// Lists made only of literals become a single literal array, and lists of IP
+/// addresses and CIDR blocks a [`CidrSet`].
+fn fold_list(items: Vec<Expr>) -> Expr {
+    if !items.is_empty() && items.iter().all(|item| matches!(item, Expr::Ip(_))) {
+        let networks = items
+            .into_iter()
+            .map(|item| match item {
+                Expr::Ip(network) => network,
+                _ => unreachable!(),
+            })
+            .collect();
+        Expr::CidrSet(CidrSet::new(networks))
+    } else if items.iter().all(|item| matches!(item, Expr::Literal(_))) {
+        let values = items
+            .into_iter()
+            .map(|item| match item {
+                Expr::Literal(value) => value,
+                _ => unreachable!(),
+            })
+            .collect();
+        Expr::Literal(serde_json::Value::Array(values))
+    } else {
+        Expr::List(items)
+    }
+}
+
+/// Parses the simple conditions of `condition` and checks them as [`check_expr`]
+/// does, reporting the first syntax or check error.
+pub(crate) fn check_condition(condition: &Condition) -> Result<(), RuleEngineError> {
+    let check_source = |source: &str| check_expr(&parser::parse_expression(source)?);
+    match condition {
+        Condition::Simple(source) => check_source(source),
+        Condition::And(conditions)
+        | Condition::Or(conditions)
+        | Condition::Xor(conditions)
+        | Condition::AtLeast { conditions, .. }
+        | Condition::AtMost { conditions, .. } => conditions.iter().try_for_each(check_condition),
+        Condition::Not(condition) => check_condition(condition),
+        Condition::Any {
+            field, condition, ..
+        }
+        | Condition::All {
+            field, condition, ..
+        }
+        | Condition::None {
+            field, condition, ..
+        } => {
+            check_source(field)?;
+            check_condition(condition)
+        }
+        Condition::Ref(name) => Err(unresolved_reference(name)),
+    }
+}
+
+/// Checks an outcome the way [`check_condition`] checks a condition: its
+/// expression or templates must compile, and `value`, `expr` and `type` must fit
+/// together.
+pub(crate) fn check_outcome(outcome: &Outcome) -> Result<(), RuleEngineError> {
+    let mut tera = template_engine();
+    validate_value(&compile_outcome(
+        outcome,
+        "outcome",
+        CompileOptions::default(),
+        &mut tera,
+    ))
+}
+
+/// Rejects what can be rejected before evaluation: literal regular expressions
+/// that do not compile, and `between` ranges whose literal bounds are of
+/// different types, out of order or empty, e.g. `age between [18, 18)`.
+fn check_expr(expr: &Expr) -> Result<(), RuleEngineError> {
+    match expr {
+        Expr::Compare {
+            op: CompareOp::Matches | CompareOp::NotMatches,
+            left,
+            right,
+        } => {
+            check_expr(left)?;
+            if let Expr::Literal(serde_json::Value::String(pattern)) = right.as_ref() {
+                Pattern::new(pattern).map_err(|e| {
+                    RuleEngineError::ParseError(format!(
+                        "Invalid regular expression '{}': {}",
+                        pattern, e
+                    ))
+                })?;
+            }
+            check_expr(right)
+        }
+        Expr::Compare {
+            op: CompareOp::Satisfies,
+            left,
+            right,
+        } => {
+            check_expr(left)?;
+            if let Expr::Literal(serde_json::Value::String(requirement)) = right.as_ref() {
+                semver::VersionReq::parse(requirement).map_err(|e| {
+                    RuleEngineError::ParseError(format!(
+                        "Invalid version requirement '{}': {}",
+                        requirement, e
+                    ))
+                })?;
+            }
+            check_expr(right)
+        }
+        Expr::Between {
+            value,
+            low,
+            high,
+            low_inclusive,
+            high_inclusive,
+        } => {
+            check_expr(value)?;
+            check_expr(low)?;
+            check_expr(high)?;
+            let ordering = match (low.as_ref(), high.as_ref()) {
+                (
+                    Expr::Literal(serde_json::Value::Number(a)),
+                    Expr::Literal(serde_json::Value::Number(b)),
+                ) => Number::from_json(a, false).partial_cmp(&Number::from_json(b, false)),
+                (Expr::DateTime(a), Expr::DateTime(b)) => Some(a.cmp(b)),
+                (Expr::Duration(a), Expr::Duration(b)) => Some(a.cmp(b)),
+                (Expr::Version(a), Expr::Version(b)) => Some(a.cmp_precedence(b)),
+                _ if is_constant(low) && is_constant(high) => None,
+                // Bounds computed at evaluation time are checked then.
+                _ => return Ok(()),
+            };
+            let range = format!(
+                "{}{}, {}{}",
+                if *low_inclusive { '[' } else { '(' },
+                describe_constant(low),
+                describe_constant(high),
+                if *high_inclusive { ']' } else { ')' }
+            );
+            match ordering {
+                None => Err(RuleEngineError::ParseError(format!(
+                    "Range {} must have two numbers, dates, durations or versions as bounds",
+                    range
+                ))),
+                Some(Ordering::Greater) => Err(RuleEngineError::ParseError(format!(
+                    "Range {} has a lower bound greater than its upper bound",
+                    range
+                ))),
+                Some(Ordering::Equal) if !(*low_inclusive && *high_inclusive) => Err(
+                    RuleEngineError::ParseError(format!("Range {} is empty", range)),
+                ),
+                _ => Ok(()),
+            }
+        }
+        Expr::Compare { left, right, .. }
+        | Expr::Arithmetic { left, right, .. }
+        | Expr::Coalesce { left, right } => {
+            check_expr(left)?;
+            check_expr(right)
+        }
+        Expr::Call { args: items, .. } | Expr::List(items) => items.iter().try_for_each(check_expr),
+        Expr::Negate(operand) | Expr::Check { operand, .. } => check_expr(operand),
+        Expr::Literal(_)
+        | Expr::Pattern(_)
+        | Expr::DateTime(_)
+        | Expr::Duration(_)
+        | Expr::Version(_)
+        | Expr::Ip(_)
+        | Expr::CidrSet(_)
+        | Expr::Field(_)
+        | Expr::Constant(_)
+        | Expr::Bareword(_) => Ok(()),
+    }
+}
+
+fn is_constant(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Literal(_) | Expr::DateTime(_) | Expr::Duration(_) | Expr::Version(_)
+    )
+}
+
+fn describe_constant(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal(value) => value.to_string(),
+        Expr::DateTime(datetime) => datetime.to_rfc3339(),
+        Expr::Duration(duration) => value::format_duration(*duration),
+        Expr::Version(version) => version.to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+fn validate_condition(condition: &CompiledCondition) -> Result<(), RuleEngineError> {
+    match condition {
+        CompiledCondition::Expr(_) => Ok(()),
+        CompiledCondition::And(conditions)
+        | CompiledCondition::Or(conditions)
+        | CompiledCondition::Xor(conditions)
+        | CompiledCondition::AtLeast { conditions, .. }
+        | CompiledCondition::AtMost { conditions, .. } => {
+            conditions.iter().try_for_each(validate_condition)
+        }
+        CompiledCondition::Not(condition) | CompiledCondition::Quantifier { condition, .. } => {
+            validate_condition(condition)
+        }
+        CompiledCondition::Invalid(e) => Err(e.clone()),
+    }
+}
+
+fn validate_value(value: &CompiledValue) -> Result<(), RuleEngineError> {
+    match value {
+        CompiledValue::Literal(_) | CompiledValue::Template(_) | CompiledValue::Expr(_) => Ok(()),
+        CompiledValue::Array(items) => items.iter().try_for_each(validate_value),
+        CompiledValue::Object(entries) => entries.iter().try_for_each(|(key, value)| {
+            validate_value(key)?;
+            validate_value(value)
+        }),
+        CompiledValue::Invalid(e) => Err(e.clone()),
+    }
+}
+
+/// Compiles an outcome value, registering every string that holds template
+/// syntax, however deeply nested, as a template named after `name` and its path.
+/// Values without templates stay literal.
+fn compile_value(
+    value: &serde_json::Value,
+    name: &str,
+    templated_keys: bool,
+    tera: &mut Tera,
+) -> CompiledValue {
+    if !has_template(value, templated_keys) {
+        return CompiledValue::Literal(value.clone());
+    }
+    match value {
+        serde_json::Value::String(template) => match tera.add_raw_template(name, template) {
+            Ok(()) => CompiledValue::Template(name.to_string()),
+            Err(e) => CompiledValue::Invalid(RuleEngineError::ParseError(format!(
+                "Invalid template '{}': {}",
+                template, e
+            ))),
+        },
+        serde_json::Value::Array(items) => CompiledValue::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    compile_value(item, &format!("{}/{}", name, i), templated_keys, tera)
+                })
+                .collect(),
+        ),
+        serde_json::Value::Object(map) => CompiledValue::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    // Keys are escaped as in a JSON pointer, so `~key` cannot clash.
+                    let segment = key.replace('~', "~0").replace('/', "~1");
+                    let key = serde_json::Value::String(key.clone());
+                    let key = if templated_keys {
+                        compile_value(&key, &format!("{}/~key/{}", name, segment), true, tera)
+                    } else {
+                        CompiledValue::Literal(key)
+                    };
+                    let value = compile_value(
+                        value,
+                        &format!("{}/{}", name, segment),
+                        templated_keys,
+                        tera,
+                    );
+                    (key, value)
+                })
+                .collect(),
+        ),
+        other => CompiledValue::Literal(other.clone()),
+    }
+}
+
+fn has_template(value: &serde_json::Value, templated_keys: bool) -> bool {
+    match value {
+        serde_json::Value::String(s) => is_template(s),
+        serde_json::Value::Array(items) => {
+            items.iter().any(|item| has_template(item, templated_keys))
+        }
+        serde_json::Value::Object(map) => map.iter().any(|(key, value)| {
+            (templated_keys && is_template(key)) || has_template(value, templated_keys)
+        }),
+        _ => false,
+    }
+}
+
+/// Tera filter resolving a JSON Pointer against its input, e.g.
+/// `{{ user | pointer(path="/address/country") }}`.
+fn pointer_filter(
+    value: &serde_json::Value,
+    args: &HashMap<String, serde_json::Value>,
+) -> tera::Result<serde_json::Value> {
+    let path = args
+        .get("path")
+        .and_then(|path| path.as_str())
+        .ok_or_else(|| tera::Error::msg("Filter `pointer` expects a string `path` argument"))?;
+    value
+        .pointer(path)
+        .cloned()
+        .ok_or_else(|| tera::Error::msg(format!("JSON pointer '{}' not found", path)))
+}
+
+/// Strings without Tera delimiters render to themselves, so they need no template.
+fn is_template(value: &str) -> bool {
+    value.contains("{{") || value.contains("{%") || value.contains("{#")
+}
@@ -1,22 +1,29 @@
 use crate::error::RuleEngineError;
 use crate::parser;
+use crate::types::RuleSet;
 use std::fs;
 
 pub struct Config;
 
 impl Config {
+    /// Loads rules from a file that declares no constants, see
+    /// [`parser::parse_rules`].
     pub fn load_from_file(path: &str) -> Result<Vec<crate::types::Rule>, RuleEngineError> {
+        Self::load_rule_set_from_file(path).and_then(parser::into_rules)
+    }
+
+    /// Loads rules together with the constants declared by the file.
+    pub fn load_rule_set_from_file(path: &str) -> Result<RuleSet, RuleEngineError> {
         let content = fs::read_to_string(path)
             .map_err(|e| RuleEngineError::ConfigError(format!("Failed to read file: {}", e)))?;
-        parser::parse_rules(&content)
+        parser::parse_rule_set(&content)
     }
 
     pub fn reload_from_file(
         path: &str,
         engine: &mut crate::engine::RuleEngine,
     ) -> Result<(), RuleEngineError> {
-        let rules = Self::load_from_file(path)?;
-        engine.rules = rules;
-        Ok(())
+        let rule_set = Self::load_rule_set_from_file(path)?;
+        engine.update_rule_set(rule_set)
     }
 }
@@ -1,50 +1,251 @@
+use crate::compiled::{
+    CompileOptions, CompiledCondition, CompiledOutcome, CompiledRuleSet, CompiledValue, Quantifier,
+};
 use crate::error::RuleEngineError;
-use crate::types::{Condition, Outcome, Rule};
-use std::cell::RefCell;
+use crate::functions;
+use crate::network;
+use crate::number::Number;
+use crate::parser::{ArithOp, CheckOp, CidrSet, CompareOp, Expr, FieldPath, PathSegment, Pattern};
+use crate::resolution::{self, ResolutionStrategy};
+use crate::types::{Outcome, OutcomeType, Rule, RuleSet};
+use crate::value::{self, Value};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use tera::{Context, Tera};
+use tera::Context;
 
 type CustomFunction =
-    Box<dyn Fn(&serde_json::Value) -> Result<serde_json::Value, RuleEngineError> + Send + Sync>;
+    Box<dyn Fn(&[serde_json::Value]) -> Result<serde_json::Value, RuleEngineError> + Send + Sync>;
+type Clock = Box<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+/// What a condition can refer to: the input, the context and the elements bound
+/// by enclosing quantifiers, innermost last.
+struct Scope<'a> {
+    input: &'a HashMap<String, serde_json::Value>,
+    context: &'a HashMap<String, serde_json::Value>,
+    bindings: Vec<(&'a str, &'a serde_json::Value)>,
+}
+
+impl<'a> Scope<'a> {
+    /// Looks up a name in the bindings, then the input, then the context.
+    fn get(&self, key: &str) -> Option<&'a serde_json::Value> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| *value)
+            .or_else(|| self.input.get(key))
+            .or_else(|| self.context.get(key))
+    }
+}
 
 pub struct RuleEngine {
-    pub rules: Vec<Rule>,
+    rules: Vec<Rule>,
+    compiled: CompiledRuleSet,
     custom_functions: HashMap<String, CustomFunction>,
     stop_on_first_match: bool,
-    tera: RefCell<Tera>, // Wrap in RefCell for interior mutability
+    strict_comparisons: bool,
+    builtins: bool,
+    clock: Clock,
+    missing_key_policy: MissingKeyPolicy,
+    decimal_arithmetic: bool,
+    /// The constants of the rule set, replaced by `update_rule_set`.
+    constants: HashMap<String, serde_json::Value>,
+    /// Constants set with `with_constants`, taking precedence over `constants`.
+    constant_overrides: HashMap<String, serde_json::Value>,
+    compile_options: CompileOptions,
+    resolution_strategy: ResolutionStrategy,
+    key_resolution_strategies: HashMap<String, ResolutionStrategy>,
+}
+
+/// How a condition treats a reference to a field that is in neither the input nor
+/// the context. `exists`, `is_null`, `is_empty` and `??` handle missing fields
+/// themselves and are unaffected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingKeyPolicy {
+    /// Fail the evaluation with `EvaluationError("Key 'x' not found")`.
+    #[default]
+    Error,
+    /// Use `null` in place of the missing value. Ordering comparisons against
+    /// `null` are false and quantifiers treat a `null` collection as empty.
+    TreatAsNull,
+    /// Make the simple condition referring to the field false.
+    ConditionFalse,
 }
 
 impl RuleEngine {
+    /// Creates an engine and compiles `rules`. Rules that fail to compile report
+    /// their error from [`RuleEngine::evaluate`]; use [`RuleEngine::update_rules`]
+    /// to reject invalid rules upfront.
     pub fn new(rules: Vec<Rule>) -> Self {
-        let mut tera = Tera::default();
-        tera.autoescape_on(vec![]);
+        let compiled = CompiledRuleSet::compile(&rules, CompileOptions::default());
         RuleEngine {
             rules,
+            compiled,
             custom_functions: HashMap::new(),
             stop_on_first_match: false,
-            tera: RefCell::new(tera),
+            strict_comparisons: false,
+            builtins: true,
+            clock: Box::new(Utc::now),
+            missing_key_policy: MissingKeyPolicy::default(),
+            decimal_arithmetic: false,
+            constants: HashMap::new(),
+            constant_overrides: HashMap::new(),
+            compile_options: CompileOptions::default(),
+            resolution_strategy: ResolutionStrategy::default(),
+            key_resolution_strategies: HashMap::new(),
         }
     }
 
-    pub fn with_function<F>(mut self, name: &str, func: F) -> Self
+    /// Creates an engine from a rule set, such as one read by
+    /// [`crate::parser::parse_rule_set`], with the rule set's constants.
+    pub fn from_rule_set(rule_set: RuleSet) -> Self {
+        let mut engine = Self::new(rule_set.rules);
+        engine.constants = rule_set.constants;
+        engine
+    }
+
+    /// Sets constants, overriding those of the rule set with the same name, e.g. to
+    /// tune thresholds per environment. Overrides are kept by
+    /// [`RuleEngine::update_rule_set`].
+    pub fn with_constants(mut self, constants: HashMap<String, serde_json::Value>) -> Self {
+        self.constant_overrides.extend(constants);
+        self
+    }
+
+    /// Registers a single-argument function, callable as `name(arg)`.
+    pub fn with_function<F>(self, name: &str, func: F) -> Self
     where
         F: Fn(&serde_json::Value) -> Result<serde_json::Value, RuleEngineError>
             + 'static
             + Send
             + Sync,
+    {
+        let function_name = name.to_string();
+        self.with_variadic_function(name, move |args| match args {
+            [arg] => func(arg),
+            _ => Err(RuleEngineError::EvaluationError(format!(
+                "Function '{}' expects 1 argument, got {}",
+                function_name,
+                args.len()
+            ))),
+        })
+    }
+
+    /// Registers a function taking any number of arguments, callable as
+    /// `name(a, b, ...)`. The function is responsible for checking its arity.
+    pub fn with_variadic_function<F>(mut self, name: &str, func: F) -> Self
+    where
+        F: Fn(&[serde_json::Value]) -> Result<serde_json::Value, RuleEngineError>
+            + 'static
+            + Send
+            + Sync,
     {
         self.custom_functions
             .insert(name.to_string(), Box::new(func));
         self
     }
 
+    /// Enables or disables the built-in functions (`length`, `lower`, `upper`, `trim`,
+    /// `abs`, `min`, `max`, `round`, `sum`, `avg`, `count`, `keys`, `coalesce`,
+    /// `to_number` and `to_string`). They are enabled by default, and a function
+    /// registered with the same name always takes precedence.
+    pub fn with_builtins(mut self, value: bool) -> Self {
+        self.builtins = value;
+        self
+    }
+
+    /// Sets the clock used for `now()` and the `within` operator, e.g. to evaluate
+    /// rules at a fixed point in time. Defaults to the system clock.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> DateTime<Utc> + 'static + Send + Sync,
+    {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets how references to missing fields are handled. Defaults to
+    /// [`MissingKeyPolicy::Error`].
+    pub fn with_missing_key_policy(mut self, policy: MissingKeyPolicy) -> Self {
+        self.missing_key_policy = policy;
+        self
+    }
+
+    /// Evaluates non-integer numbers as arbitrary-precision decimals instead of
+    /// f64, so that `0.1 + 0.2 = 0.3` holds. Integers are exact either way.
+    #[cfg(feature = "decimal")]
+    pub fn with_decimal_arithmetic(mut self, value: bool) -> Self {
+        self.decimal_arithmetic = value;
+        self
+    }
+
+    /// Also renders object keys in outcome values as templates, e.g.
+    /// `{"{{ tier }}_discount": 10}`. Only values are rendered by default.
+    pub fn with_templated_keys(mut self, value: bool) -> Self {
+        self.compile_options.templated_keys = value;
+        self.compiled = CompiledRuleSet::compile(&self.rules, self.compile_options);
+        self
+    }
+
+    /// Reads a bare identifier on the right-hand side of a comparison as a field
+    /// reference, so that `balance >= minimum_balance` compares two fields. By
+    /// default it is a string, as in `status = active`. Paths such as `limits.max`
+    /// are fields either way.
+    pub fn with_bareword_fields(mut self, value: bool) -> Self {
+        self.compile_options.bareword_fields = value;
+        self.compiled = CompiledRuleSet::compile(&self.rules, self.compile_options);
+        self
+    }
+
+    /// Sets how [`RuleEngine::evaluate_resolved`] combines outcomes sharing a key.
+    /// Defaults to [`ResolutionStrategy::HighestPriority`].
+    pub fn with_resolution_strategy(mut self, strategy: ResolutionStrategy) -> Self {
+        self.resolution_strategy = strategy;
+        self
+    }
+
+    /// Sets the resolution strategy for one outcome key, overriding the default
+    /// set with [`RuleEngine::with_resolution_strategy`].
+    pub fn with_key_resolution_strategy(mut self, key: &str, strategy: ResolutionStrategy) -> Self {
+        self.key_resolution_strategies
+            .insert(key.to_string(), strategy);
+        self
+    }
+
     pub fn with_stop_on_first_match(mut self, value: bool) -> Self {
         self.stop_on_first_match = value;
         self
     }
 
-    pub fn update_rules(&mut self, rules: Vec<Rule>) {
+    /// Rejects comparisons between values of different JSON types (such as `"3" = 3`)
+    /// with an `EvaluationError` instead of coercing them. Comparisons against `null`
+    /// are always allowed.
+    pub fn with_strict_comparisons(mut self, value: bool) -> Self {
+        self.strict_comparisons = value;
+        self
+    }
+
+    /// The rules this engine was built from, in their original order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Compiles and installs a new rule set. If any rule fails to compile the
+    /// current rules are kept and the error is returned.
+    pub fn update_rules(&mut self, rules: Vec<Rule>) -> Result<(), RuleEngineError> {
+        let compiled = CompiledRuleSet::compile(&rules, self.compile_options);
+        compiled.validate()?;
         self.rules = rules;
+        self.compiled = compiled;
+        Ok(())
+    }
+
+    /// Like [`RuleEngine::update_rules`], also replacing the rule set's constants.
+    pub fn update_rule_set(&mut self, rule_set: RuleSet) -> Result<(), RuleEngineError> {
+        self.update_rules(rule_set.rules)?;
+        self.constants = rule_set.constants;
+        Ok(())
     }
 
     pub fn evaluate(
@@ -52,20 +253,63 @@ impl RuleEngine {
         input: &HashMap<String, serde_json::Value>,
         context: &HashMap<String, serde_json::Value>,
     ) -> Result<Vec<Outcome>, RuleEngineError> {
+        let outcomes = self.evaluate_rules(input, context)?;
+        Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+    }
+
+    /// Evaluates the rules and returns one value per outcome key, combining the
+    /// values of outcomes that share a key with the key's [`ResolutionStrategy`].
+    pub fn evaluate_resolved(
+        &self,
+        input: &HashMap<String, serde_json::Value>,
+        context: &HashMap<String, serde_json::Value>,
+    ) -> Result<HashMap<String, serde_json::Value>, RuleEngineError> {
+        let mut grouped: HashMap<String, Vec<(usize, serde_json::Value)>> = HashMap::new();
+        for (index, outcome) in self.evaluate_rules(input, context)? {
+            grouped
+                .entry(outcome.key)
+                .or_default()
+                .push((index, outcome.value));
+        }
+        grouped
+            .into_iter()
+            .map(|(key, values)| {
+                let strategy = self
+                    .key_resolution_strategies
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(self.resolution_strategy);
+                let value = resolution::resolve(strategy, &key, values, self.decimal_arithmetic)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    /// The outcomes of the matching rules in priority order, each with the index of
+    /// the rule that emitted it.
+    fn evaluate_rules(
+        &self,
+        input: &HashMap<String, serde_json::Value>,
+        context: &HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<(usize, Outcome)>, RuleEngineError> {
         let mut outcomes = Vec::new();
-        let mut sorted_rules = self.rules.clone();
-        sorted_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
-
-        let tera_context = Self::create_tera_context(input, context)?;
-
-        for rule in &sorted_rules {
-            if Self::evaluate_condition(&rule.condition, input, context, &self.custom_functions)? {
-                let processed_outcome = Self::process_outcome(
-                    &rule.outcome,
-                    &mut self.tera.borrow_mut(),
-                    &tera_context,
-                )?;
-                outcomes.push(processed_outcome);
+        // Only built once a templated outcome actually needs it.
+        let mut tera_context = None;
+
+        let scope = Scope {
+            input,
+            context,
+            bindings: Vec::new(),
+        };
+
+        for rule in &self.compiled.rules {
+            let mut witnesses = Vec::new();
+            if self.evaluate_condition(&rule.condition, &scope, &mut witnesses)? {
+                for outcome in &rule.outcomes {
+                    let processed_outcome =
+                        self.process_outcome(outcome, &mut tera_context, &witnesses, &scope)?;
+                    outcomes.push((rule.index, processed_outcome));
+                }
                 if self.stop_on_first_match {
                     break;
                 }
@@ -74,11 +318,19 @@ impl RuleEngine {
         Ok(outcomes)
     }
 
+    /// The input and context, plus the constants as `constants` unless the input or
+    /// context has a value of that name.
     fn create_tera_context(
+        &self,
         input: &HashMap<String, serde_json::Value>,
         context: &HashMap<String, serde_json::Value>,
     ) -> Result<Context, RuleEngineError> {
         let mut tera_context = Context::new();
+        if !self.constants.is_empty() || !self.constant_overrides.is_empty() {
+            let mut constants = self.constants.clone();
+            constants.extend(self.constant_overrides.clone());
+            tera_context.insert("constants", &constants);
+        }
         for (key, value) in input {
             tera_context.insert(key, value);
         }
@@ -89,125 +341,1035 @@ impl RuleEngine {
     }
 
     fn process_outcome(
-        outcome: &Outcome,
-        tera: &mut Tera,
-        context: &Context,
+        &self,
+        outcome: &CompiledOutcome,
+        tera_context: &mut Option<Context>,
+        witnesses: &[(String, serde_json::Value)],
+        scope: &Scope,
     ) -> Result<Outcome, RuleEngineError> {
-        if let serde_json::Value::String(template) = &outcome.value {
-            let rendered = tera.render_str(template, context).map_err(|e| {
-                RuleEngineError::EvaluationError(format!("Template rendering failed: {}", e))
-            })?;
-            Ok(Outcome {
-                key: outcome.key.clone(),
-                value: serde_json::Value::String(rendered),
-            })
-        } else {
-            Ok(outcome.clone())
+        let value = match &outcome.value {
+            CompiledValue::Literal(value) => value.clone(),
+            // Evaluated like a condition, with the witnesses of `Any` in scope.
+            CompiledValue::Expr(expr) => {
+                let scope = Scope {
+                    input: scope.input,
+                    context: scope.context,
+                    bindings: witnesses
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value))
+                        .collect(),
+                };
+                self.evaluate_expr(expr, &scope)?.into_json()
+            }
+            value => {
+                if tera_context.is_none() {
+                    *tera_context = Some(self.create_tera_context(scope.input, scope.context)?);
+                }
+                let mut tera_context = Cow::Borrowed(tera_context.as_ref().unwrap());
+                if !witnesses.is_empty() {
+                    let tera_context = tera_context.to_mut();
+                    for (name, value) in witnesses {
+                        tera_context.insert(name, value);
+                    }
+                }
+                self.render_value(outcome, value, &tera_context)?
+            }
+        };
+        Ok(Outcome {
+            key: outcome.key.clone(),
+            value,
+            ..Default::default()
+        })
+    }
+
+    /// Renders the templates of an outcome value, converting what each template
+    /// renders to the outcome's type.
+    fn render_value(
+        &self,
+        outcome: &CompiledOutcome,
+        value: &CompiledValue,
+        tera_context: &Context,
+    ) -> Result<serde_json::Value, RuleEngineError> {
+        match value {
+            CompiledValue::Literal(value) => Ok(value.clone()),
+            CompiledValue::Template(name) => {
+                let rendered = self.render_template(name, tera_context)?;
+                match outcome.value_type {
+                    None | Some(OutcomeType::String) => Ok(serde_json::Value::String(rendered)),
+                    Some(value_type) => Self::convert_rendered(outcome, value_type, &rendered),
+                }
+            }
+            CompiledValue::Array(items) => items
+                .iter()
+                .map(|item| self.render_value(outcome, item, tera_context))
+                .collect::<Result<_, _>>()
+                .map(serde_json::Value::Array),
+            CompiledValue::Object(entries) => {
+                let mut map = serde_json::Map::with_capacity(entries.len());
+                for (key, value) in entries {
+                    // Keys are strings whatever the outcome's type.
+                    let key = match key {
+                        CompiledValue::Template(name) => {
+                            self.render_template(name, tera_context)?
+                        }
+                        key => match self.render_value(outcome, key, tera_context)? {
+                            serde_json::Value::String(key) => key,
+                            other => other.to_string(),
+                        },
+                    };
+                    if map.contains_key(&key) {
+                        return Err(RuleEngineError::EvaluationError(format!(
+                            "Outcome '{}' renders the object key '{}' more than once",
+                            outcome.key, key
+                        )));
+                    }
+                    map.insert(key, self.render_value(outcome, value, tera_context)?);
+                }
+                Ok(serde_json::Value::Object(map))
+            }
+            CompiledValue::Expr(_) => unreachable!("expressions only make up whole outcomes"),
+            CompiledValue::Invalid(e) => Err(e.clone()),
         }
     }
 
+    fn render_template(
+        &self,
+        name: &str,
+        tera_context: &Context,
+    ) -> Result<String, RuleEngineError> {
+        self.compiled.tera.render(name, tera_context).map_err(|e| {
+            RuleEngineError::EvaluationError(format!("Template rendering failed: {}", e))
+        })
+    }
+
+    fn convert_rendered(
+        outcome: &CompiledOutcome,
+        value_type: OutcomeType,
+        rendered: &str,
+    ) -> Result<serde_json::Value, RuleEngineError> {
+        let text = rendered.trim();
+        let converted = match value_type {
+            OutcomeType::String => Some(serde_json::Value::String(rendered.to_string())),
+            OutcomeType::Number => text
+                .parse::<serde_json::Number>()
+                .ok()
+                .map(serde_json::Value::Number),
+            OutcomeType::Bool => text.parse::<bool>().ok().map(serde_json::Value::Bool),
+            OutcomeType::Json => serde_json::from_str(text).ok(),
+        };
+        converted.ok_or_else(|| {
+            RuleEngineError::EvaluationError(format!(
+                "Outcome '{}' rendered '{}', which is not a valid {}",
+                outcome.key,
+                rendered,
+                value_type.as_str()
+            ))
+        })
+    }
+
+    /// Evaluates a condition, recording the elements matched by `Any` quantifiers in
+    /// `witnesses`. Witnesses of a condition that turns out false are discarded.
     fn evaluate_condition(
-        condition: &Condition,
-        input: &HashMap<String, serde_json::Value>,
-        context: &HashMap<String, serde_json::Value>,
-        custom_functions: &HashMap<String, CustomFunction>,
+        &self,
+        condition: &CompiledCondition,
+        scope: &Scope,
+        witnesses: &mut Vec<(String, serde_json::Value)>,
+    ) -> Result<bool, RuleEngineError> {
+        let bound = witnesses.len();
+        let result = self.evaluate_condition_inner(condition, scope, witnesses)?;
+        if !result {
+            witnesses.truncate(bound);
+        }
+        Ok(result)
+    }
+
+    fn evaluate_condition_inner(
+        &self,
+        condition: &CompiledCondition,
+        scope: &Scope,
+        witnesses: &mut Vec<(String, serde_json::Value)>,
     ) -> Result<bool, RuleEngineError> {
         match condition {
-            Condition::Simple(expr) => {
-                let parts: Vec<&str> = expr.split_whitespace().collect();
-                if parts.len() != 3 {
-                    return Err(RuleEngineError::EvaluationError(
-                        "Simple condition must have 3 parts: key operator value".to_string(),
-                    ));
-                }
-
-                let left = parts[0];
-                let operator = parts[1];
-                let right = parts[2];
-
-                let input_value = if left.contains('(') && left.ends_with(')') {
-                    let func_name = left[..left.find('(').unwrap()].to_string();
-                    let arg_key = &left[left.find('(').unwrap() + 1..left.len() - 1];
-                    let arg_value = input
-                        .get(arg_key)
-                        .or_else(|| context.get(arg_key))
-                        .ok_or_else(|| {
-                            RuleEngineError::EvaluationError(format!("Key '{}' not found", arg_key))
-                        })?;
-                    if let Some(func) = custom_functions.get(&func_name) {
-                        func(arg_value)?
-                    } else {
+            CompiledCondition::Expr(expr) => self.evaluate_predicate(expr, scope),
+            CompiledCondition::And(conditions) => {
+                for cond in conditions {
+                    if !self.evaluate_condition(cond, scope, witnesses)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            CompiledCondition::Or(conditions) => {
+                for cond in conditions {
+                    if self.evaluate_condition(cond, scope, witnesses)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            CompiledCondition::Not(condition) => {
+                Ok(!self.evaluate_condition(condition, scope, witnesses)?)
+            }
+            CompiledCondition::Xor(conditions) => {
+                let mut matched = false;
+                for cond in conditions {
+                    if self.evaluate_condition(cond, scope, witnesses)? {
+                        if matched {
+                            return Ok(false);
+                        }
+                        matched = true;
+                    }
+                }
+                Ok(matched)
+            }
+            CompiledCondition::AtLeast { n, conditions } => {
+                let mut matched = 0;
+                for (i, cond) in conditions.iter().enumerate() {
+                    if matched >= *n {
+                        break;
+                    }
+                    // Stop once the remaining conditions cannot reach `n`.
+                    if matched + (conditions.len() - i) < *n {
+                        return Ok(false);
+                    }
+                    if self.evaluate_condition(cond, scope, witnesses)? {
+                        matched += 1;
+                    }
+                }
+                Ok(matched >= *n)
+            }
+            CompiledCondition::AtMost { n, conditions } => {
+                let mut matched = 0;
+                for (i, cond) in conditions.iter().enumerate() {
+                    // Stop once even the remaining conditions cannot exceed `n`.
+                    if matched + (conditions.len() - i) <= *n {
+                        return Ok(true);
+                    }
+                    if self.evaluate_condition(cond, scope, witnesses)? {
+                        matched += 1;
+                        if matched > *n {
+                            return Ok(false);
+                        }
+                    }
+                }
+                Ok(true)
+            }
+            CompiledCondition::Quantifier {
+                quantifier,
+                field,
+                collection,
+                binding,
+                condition,
+            } => {
+                let items = self.evaluate_expr(collection, scope)?;
+                if items.is_missing() {
+                    return Ok(false);
+                }
+                let items = match items.to_json() {
+                    Cow::Borrowed(serde_json::Value::Array(items)) => Cow::Borrowed(items),
+                    Cow::Owned(serde_json::Value::Array(items)) => Cow::Owned(items),
+                    // A null collection, e.g. a missing field under
+                    // `MissingKeyPolicy::TreatAsNull`, has no elements.
+                    other if other.is_null() => Cow::Owned(Vec::new()),
+                    other => {
                         return Err(RuleEngineError::EvaluationError(format!(
-                            "Unknown function '{}'",
-                            func_name
+                            "Quantifier over '{}' expects an array, got {}",
+                            field, other
                         )));
                     }
-                } else {
-                    input
-                        .get(left)
-                        .or_else(|| context.get(left))
-                        .ok_or_else(|| {
-                            RuleEngineError::EvaluationError(format!("Key '{}' not found", left))
-                        })?
-                        .clone()
                 };
-
-                match operator {
-                    ">" | "<" | ">=" | "<=" => {
-                        // Try to convert input_value to f64, handling both numbers and strings
-                        let input_num = match input_value {
-                            serde_json::Value::Number(n) => n.as_f64().ok_or_else(|| {
-                                RuleEngineError::EvaluationError("Invalid number".to_string())
-                            })?,
-                            serde_json::Value::String(s) => s.parse::<f64>().map_err(|e| {
-                                RuleEngineError::EvaluationError(format!(
-                                    "Cannot parse '{}' as number: {}",
-                                    s, e
-                                ))
-                            })?,
-                            _ => {
-                                return Err(RuleEngineError::EvaluationError(
-                                    "Value must be a number or numeric string".to_string(),
-                                ));
-                            }
-                        };
-                        let cond_num = right.parse::<f64>().map_err(|e| {
-                            RuleEngineError::EvaluationError(format!(
-                                "Right-hand side '{}' must be a number: {}",
-                                right, e
-                            ))
-                        })?;
-                        match operator {
-                            ">" => Ok(input_num > cond_num),
-                            "<" => Ok(input_num < cond_num),
-                            ">=" => Ok(input_num >= cond_num),
-                            "<=" => Ok(input_num <= cond_num),
-                            _ => unreachable!(),
+                let mut inner = Scope {
+                    input: scope.input,
+                    context: scope.context,
+                    bindings: scope.bindings.clone(),
+                };
+                inner.bindings.push((binding, &serde_json::Value::Null));
+                for item in items.iter() {
+                    *inner.bindings.last_mut().unwrap() = (binding, item);
+                    let matched = self.evaluate_condition(condition, &inner, witnesses)?;
+                    match quantifier {
+                        Quantifier::Any if matched => {
+                            witnesses.push((binding.clone(), item.clone()));
+                            return Ok(true);
                         }
+                        Quantifier::All if !matched => return Ok(false),
+                        Quantifier::None if matched => return Ok(false),
+                        _ => {}
                     }
-                    "=" => Ok(input_value == serde_json::Value::String(right.to_string())),
-                    _ => Err(RuleEngineError::EvaluationError(format!(
-                        "Unsupported operator '{}'",
-                        operator
+                }
+                Ok(*quantifier != Quantifier::Any)
+            }
+            CompiledCondition::Invalid(e) => Err(e.clone()),
+        }
+    }
+
+    fn evaluate_predicate(&self, expr: &Expr, scope: &Scope) -> Result<bool, RuleEngineError> {
+        match self.evaluate_expr(expr, scope)? {
+            Value::Json(value) if value.is_boolean() => Ok(value.as_bool() == Some(true)),
+            Value::Missing => Ok(false),
+            other => Err(RuleEngineError::EvaluationError(format!(
+                "Condition must evaluate to a boolean, got {}",
+                other.into_json()
+            ))),
+        }
+    }
+
+    fn evaluate_expr<'a>(
+        &self,
+        expr: &'a Expr,
+        scope: &Scope<'a>,
+    ) -> Result<Value<'a>, RuleEngineError> {
+        match expr {
+            Expr::Literal(value) => Ok(Value::borrowed(value)),
+            Expr::DateTime(datetime) => Ok(Value::DateTime(*datetime)),
+            Expr::Duration(duration) => Ok(Value::Duration(*duration)),
+            Expr::Version(version) => Ok(Value::Version(version.clone())),
+            Expr::Ip(network) => Ok(Value::Ip(*network)),
+            Expr::CidrSet(set) => Ok(Value::owned(serde_json::Value::Array(
+                set.networks()
+                    .iter()
+                    .map(|network| serde_json::Value::String(network::format_network(network)))
+                    .collect(),
+            ))),
+            Expr::Pattern(pattern) => Ok(Value::owned(serde_json::Value::String(
+                pattern.as_str().to_string(),
+            ))),
+            Expr::Field(path) => match Self::lookup(path, scope) {
+                Some(value) => Ok(Value::borrowed(value)),
+                None => match self.missing_key_policy {
+                    MissingKeyPolicy::Error => Err(RuleEngineError::EvaluationError(format!(
+                        "Key '{}' not found",
+                        path
                     ))),
+                    MissingKeyPolicy::TreatAsNull => Ok(Value::owned(serde_json::Value::Null)),
+                    MissingKeyPolicy::ConditionFalse => Ok(Value::Missing),
+                },
+            },
+            Expr::Call { name, args }
+                if name == "now" && !self.custom_functions.contains_key(name) =>
+            {
+                if !args.is_empty() {
+                    return Err(RuleEngineError::EvaluationError(format!(
+                        "Function 'now' expects 0 arguments, got {}",
+                        args.len()
+                    )));
                 }
+                Ok(Value::DateTime((self.clock)()))
             }
-            Condition::And(conditions) => {
-                for cond in conditions {
-                    if !Self::evaluate_condition(cond, input, context, custom_functions)? {
+            Expr::Call { name, args } => {
+                let builtin = self.builtins.then(|| functions::builtin(name)).flatten();
+                if !self.custom_functions.contains_key(name) && builtin.is_none() {
+                    return Err(RuleEngineError::EvaluationError(format!(
+                        "Unknown function '{}'",
+                        name
+                    )));
+                }
+                let mut arg_values = Vec::with_capacity(args.len());
+                for arg in args {
+                    match self.evaluate_expr(arg, scope)? {
+                        Value::Missing => return Ok(Value::Missing),
+                        value => arg_values.push(value.into_json()),
+                    }
+                }
+                match self.custom_functions.get(name) {
+                    Some(func) => func(&arg_values),
+                    None => builtin.unwrap()(&arg_values, self.decimal_arithmetic),
+                }
+                .map(Value::owned)
+            }
+            Expr::Constant(name) => self
+                .constant_overrides
+                .get(name)
+                .or_else(|| self.constants.get(name))
+                .map(|value| Value::owned(value.clone()))
+                .ok_or_else(|| {
+                    RuleEngineError::EvaluationError(format!("Unknown constant '${}'", name))
+                }),
+            Expr::Bareword(_) => unreachable!("bare identifiers are resolved when rules compile"),
+            Expr::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    match self.evaluate_expr(item, scope)? {
+                        Value::Missing => return Ok(Value::Missing),
+                        value => values.push(value.into_json()),
+                    }
+                }
+                Ok(Value::owned(serde_json::Value::Array(values)))
+            }
+            Expr::Arithmetic { op, left, right } => {
+                let left = self.evaluate_expr(left, scope)?;
+                let right = self.evaluate_expr(right, scope)?;
+                if left.is_missing() || right.is_missing() {
+                    return Ok(Value::Missing);
+                }
+                if left.is_temporal() || right.is_temporal() {
+                    return Self::temporal_arithmetic(*op, &left, &right);
+                }
+                self.arithmetic(*op, &left, &right)
+            }
+            Expr::Negate(operand) => {
+                let value = self.evaluate_expr(operand, scope)?;
+                match value {
+                    Value::Duration(duration) => return Ok(Value::Duration(-duration)),
+                    Value::Missing => return Ok(Value::Missing),
+                    _ => {}
+                }
+                Self::number_value(self.operand(&value)?.negate())
+            }
+            Expr::Compare { op, left, right } => {
+                let input_value = self.evaluate_expr(left, scope)?;
+                if let Expr::Pattern(pattern) = right.as_ref() {
+                    if input_value.is_missing() {
+                        return Ok(Value::Missing);
+                    }
+                    return Self::match_pattern(*op, &input_value.to_json(), pattern)
+                        .map(|result| Value::owned(serde_json::Value::Bool(result)));
+                }
+                if let Expr::CidrSet(set) = right.as_ref() {
+                    if input_value.is_missing() {
+                        return Ok(Value::Missing);
+                    }
+                    return Self::in_cidr_set(*op, &input_value, set)
+                        .map(|result| Value::owned(serde_json::Value::Bool(result)));
+                }
+                let cond_value = self.evaluate_expr(right, scope)?;
+                if input_value.is_missing() || cond_value.is_missing() {
+                    return Ok(Value::Missing);
+                }
+                self.compare_values(*op, &input_value, &cond_value)
+                    .map(|result| Value::owned(serde_json::Value::Bool(result)))
+            }
+            Expr::Between {
+                value,
+                low,
+                high,
+                low_inclusive,
+                high_inclusive,
+            } => {
+                let value = self.evaluate_expr(value, scope)?;
+                let low = self.evaluate_expr(low, scope)?;
+                let high = self.evaluate_expr(high, scope)?;
+                if value.is_missing() || low.is_missing() || high.is_missing() {
+                    return Ok(Value::Missing);
+                }
+                let low_op = if *low_inclusive {
+                    CompareOp::Ge
+                } else {
+                    CompareOp::Gt
+                };
+                let high_op = if *high_inclusive {
+                    CompareOp::Le
+                } else {
+                    CompareOp::Lt
+                };
+                let result = self.compare_values(low_op, &value, &low)?
+                    && self.compare_values(high_op, &value, &high)?;
+                Ok(Value::owned(serde_json::Value::Bool(result)))
+            }
+            Expr::Coalesce { left, right } => {
+                let value = self.evaluate_optional(left, scope)?;
+                if value.is_null() {
+                    return self.evaluate_expr(right, scope);
+                }
+                Ok(value)
+            }
+            Expr::Check { op, operand } => {
+                let value = self.evaluate_optional(operand, scope)?;
+                let result = match op {
+                    CheckOp::Exists => !value.is_missing(),
+                    CheckOp::IsNull => value.is_null(),
+                    CheckOp::IsEmpty => match value.to_json().as_ref() {
+                        serde_json::Value::Null => true,
+                        serde_json::Value::String(s) => s.is_empty(),
+                        serde_json::Value::Array(items) => items.is_empty(),
+                        serde_json::Value::Object(map) => map.is_empty(),
+                        _ => false,
+                    },
+                };
+                Ok(Value::owned(serde_json::Value::Bool(result)))
+            }
+        }
+    }
+
+    /// Evaluates an operand that may name a missing field, which yields
+    /// [`Value::Missing`] whatever the engine's missing-key policy.
+    fn evaluate_optional<'a>(
+        &self,
+        expr: &'a Expr,
+        scope: &Scope<'a>,
+    ) -> Result<Value<'a>, RuleEngineError> {
+        match expr {
+            Expr::Field(path) => {
+                Ok(Self::lookup(path, scope).map_or(Value::Missing, Value::borrowed))
+            }
+            other => self.evaluate_expr(other, scope),
+        }
+    }
+
+    /// Resolves a field path against the scope.
+    fn lookup<'a>(path: &FieldPath, scope: &Scope<'a>) -> Option<&'a serde_json::Value> {
+        let root = match path.segments.first()? {
+            PathSegment::Key(key) => scope.get(key)?,
+            PathSegment::Index(_) => return None,
+        };
+        path.resolve_in(root)
+    }
+
+    fn compare(
+        &self,
+        op: CompareOp,
+        input_value: &serde_json::Value,
+        cond_value: &serde_json::Value,
+    ) -> Result<bool, RuleEngineError> {
+        match op {
+            CompareOp::Gt | CompareOp::Lt | CompareOp::Ge | CompareOp::Le => {
+                if self.strict_comparisons && !Self::same_type(input_value, cond_value) {
+                    return Err(Self::type_mismatch(op, input_value, cond_value));
+                }
+                let input_num = self.as_number(input_value)?;
+                let cond_num = self.as_number(cond_value)?;
+                match op {
+                    CompareOp::Gt => Ok(input_num > cond_num),
+                    CompareOp::Lt => Ok(input_num < cond_num),
+                    CompareOp::Ge => Ok(input_num >= cond_num),
+                    CompareOp::Le => Ok(input_num <= cond_num),
+                    _ => unreachable!(),
+                }
+            }
+            CompareOp::Within | CompareOp::Satisfies | CompareOp::InCidr => {
+                Err(Self::type_mismatch(op, input_value, cond_value))
+            }
+            CompareOp::Eq => self.values_equal(op, input_value, cond_value),
+            CompareOp::Ne => self
+                .values_equal(op, input_value, cond_value)
+                .map(|equal| !equal),
+            CompareOp::In => self.contains(op, cond_value, input_value),
+            CompareOp::NotIn => self
+                .contains(op, cond_value, input_value)
+                .map(|found| !found),
+            CompareOp::Contains => self.contains(op, input_value, cond_value),
+            CompareOp::Matches | CompareOp::NotMatches => {
+                let serde_json::Value::String(pattern) = cond_value else {
+                    return Err(Self::type_mismatch(op, input_value, cond_value));
+                };
+                // Literal patterns are compiled with the rule; only patterns read
+                // from fields are compiled here.
+                let pattern = Pattern::new(pattern).map_err(|e| {
+                    RuleEngineError::EvaluationError(format!(
+                        "Invalid regular expression '{}': {}",
+                        pattern, e
+                    ))
+                })?;
+                Self::match_pattern(op, input_value, &pattern)
+            }
+            CompareOp::StartsWith | CompareOp::EndsWith => match (input_value, cond_value) {
+                (serde_json::Value::Array(items), needle) => {
+                    let item = if op == CompareOp::StartsWith {
+                        items.first()
+                    } else {
+                        items.last()
+                    };
+                    match item {
+                        Some(item) => self.values_equal(op, item, needle),
+                        None => Ok(false),
+                    }
+                }
+                (haystack, needle) => match (Self::as_text(haystack), Self::as_text(needle)) {
+                    (Some(haystack), Some(needle)) => Ok(if op == CompareOp::StartsWith {
+                        haystack.starts_with(needle.as_ref())
+                    } else {
+                        haystack.ends_with(needle.as_ref())
+                    }),
+                    _ => Err(Self::type_mismatch(op, input_value, cond_value)),
+                },
+            },
+        }
+    }
+
+    fn compare_values(
+        &self,
+        op: CompareOp,
+        left: &Value,
+        right: &Value,
+    ) -> Result<bool, RuleEngineError> {
+        // Nothing is ordered relative to null, e.g. a missing field under
+        // `MissingKeyPolicy::TreatAsNull`.
+        if matches!(
+            op,
+            CompareOp::Gt | CompareOp::Lt | CompareOp::Ge | CompareOp::Le
+        ) && (left.is_null() || right.is_null())
+        {
+            return Ok(false);
+        }
+        // Decimals computed by arithmetic are compared as they are.
+        if let (Value::Number(_), _) | (_, Value::Number(_)) = (left, right) {
+            let number = |value: &Value| match value {
+                Value::Number(number) => Some(number.clone()),
+                Value::Json(json) => json.as_number().map(|number| self.number(number)),
+                _ => None,
+            };
+            if let (Some(a), Some(b)) = (number(left), number(right)) {
+                match op {
+                    CompareOp::Eq => return Ok(a == b),
+                    CompareOp::Ne => return Ok(a != b),
+                    CompareOp::Gt => return Ok(a > b),
+                    CompareOp::Lt => return Ok(a < b),
+                    CompareOp::Ge => return Ok(a >= b),
+                    CompareOp::Le => return Ok(a <= b),
+                    _ => {}
+                }
+            }
+        }
+        if matches!(
+            op,
+            CompareOp::Within | CompareOp::Satisfies | CompareOp::InCidr
+        ) || left.is_typed()
+            || right.is_typed()
+        {
+            self.compare_typed(op, left, right)
+        } else {
+            self.compare(op, &left.to_json(), &right.to_json())
+        }
+    }
+
+    /// Compares date-times, durations, versions and IP addresses. The other operand
+    /// is coerced to the same kind: strings are parsed and numbers are read as Unix
+    /// timestamps or seconds.
+    fn compare_typed(
+        &self,
+        op: CompareOp,
+        left: &Value,
+        right: &Value,
+    ) -> Result<bool, RuleEngineError> {
+        if op == CompareOp::Within {
+            return match (left.as_datetime(), right.as_duration()) {
+                (Some(datetime), Some(duration)) => {
+                    let elapsed = (self.clock)().signed_duration_since(datetime);
+                    Ok(elapsed.abs() <= duration.abs())
+                }
+                _ => Err(Self::value_mismatch(op.as_str(), left, right)),
+            };
+        }
+        if op == CompareOp::InCidr
+            || (matches!(op, CompareOp::In | CompareOp::NotIn)
+                && (matches!(left, Value::Ip(_)) || matches!(right, Value::Ip(_))))
+        {
+            return Self::in_networks(op, left, right);
+        }
+        if op == CompareOp::Satisfies {
+            let requirement = match right {
+                Value::Json(value) => value.as_str(),
+                _ => None,
+            };
+            return match (left.as_version(), requirement) {
+                (Some(version), Some(requirement)) => semver::VersionReq::parse(requirement)
+                    .map(|requirement| requirement.matches(&version))
+                    .map_err(|e| {
+                        RuleEngineError::EvaluationError(format!(
+                            "Invalid version requirement '{}': {}",
+                            requirement, e
+                        ))
+                    }),
+                _ => Err(Self::value_mismatch(op.as_str(), left, right)),
+            };
+        }
+        let ordering = match (left, right) {
+            (Value::Ip(_), _) | (_, Value::Ip(_)) => left
+                .as_network()
+                .zip(right.as_network())
+                .map(|(a, b)| a.cmp(&b)),
+            (Value::Version(_), _) | (_, Value::Version(_)) => left
+                .as_version()
+                .zip(right.as_version())
+                .map(|(a, b)| a.cmp_precedence(&b)),
+            (Value::DateTime(_), _) | (_, Value::DateTime(_)) => left
+                .as_datetime()
+                .zip(right.as_datetime())
+                .map(|(a, b)| a.cmp(&b)),
+            _ => left
+                .as_duration()
+                .zip(right.as_duration())
+                .map(|(a, b)| a.cmp(&b)),
+        };
+        match (op, ordering) {
+            (
+                CompareOp::In
+                | CompareOp::NotIn
+                | CompareOp::Contains
+                | CompareOp::StartsWith
+                | CompareOp::EndsWith,
+                _,
+            ) => self.compare(op, &left.to_json(), &right.to_json()),
+            (CompareOp::Gt, Some(ordering)) => Ok(ordering.is_gt()),
+            (CompareOp::Lt, Some(ordering)) => Ok(ordering.is_lt()),
+            (CompareOp::Ge, Some(ordering)) => Ok(ordering.is_ge()),
+            (CompareOp::Le, Some(ordering)) => Ok(ordering.is_le()),
+            (CompareOp::Eq, Some(ordering)) => Ok(ordering.is_eq()),
+            (CompareOp::Ne, Some(ordering)) => Ok(ordering.is_ne()),
+            (CompareOp::Eq, None) if !self.strict_comparisons => Ok(false),
+            (CompareOp::Ne, None) if !self.strict_comparisons => Ok(true),
+            _ => Err(Self::value_mismatch(op.as_str(), left, right)),
+        }
+    }
+
+    /// Tests whether an address or network lies in a CIDR block, or in any block of
+    /// an array of them.
+    fn in_networks(op: CompareOp, left: &Value, right: &Value) -> Result<bool, RuleEngineError> {
+        let mismatch = || Self::value_mismatch(op.as_str(), left, right);
+        let network = left.as_network().ok_or_else(mismatch)?;
+        let found = match right.as_network() {
+            Some(block) => block.contains(&network),
+            None => match right.to_json().as_ref() {
+                serde_json::Value::Array(items) => {
+                    let mut found = false;
+                    for item in items {
+                        let block = Value::borrowed(item).as_network().ok_or_else(mismatch)?;
+                        if block.contains(&network) {
+                            found = true;
+                            break;
+                        }
+                    }
+                    found
+                }
+                _ => return Err(mismatch()),
+            },
+        };
+        Ok(found != (op == CompareOp::NotIn))
+    }
+
+    fn in_cidr_set(op: CompareOp, value: &Value, set: &CidrSet) -> Result<bool, RuleEngineError> {
+        match (op, value.as_network()) {
+            (CompareOp::In | CompareOp::InCidr, Some(network)) => Ok(set.contains(&network)),
+            (CompareOp::NotIn, Some(network)) => Ok(!set.contains(&network)),
+            _ => Err(RuleEngineError::EvaluationError(format!(
+                "Operator '{}' cannot be applied to {} and a list of networks",
+                op.as_str(),
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Applies `matches` or `not matches` to a string or number.
+    fn match_pattern(
+        op: CompareOp,
+        value: &serde_json::Value,
+        pattern: &Pattern,
+    ) -> Result<bool, RuleEngineError> {
+        match Self::as_text(value) {
+            Some(text) => Ok(pattern.is_match(&text) == (op == CompareOp::Matches)),
+            None => Err(Self::type_mismatch(
+                op,
+                value,
+                &serde_json::Value::String(pattern.as_str().to_string()),
+            )),
+        }
+    }
+
+    /// Type-aware equality. Numbers compare by value (`3 = 3.0`); outside strict mode
+    /// numeric and boolean strings are coerced (`"3" = 3`, `"true" = true`), while
+    /// strict mode rejects any comparison between different types other than `null`.
+    fn values_equal(
+        &self,
+        op: CompareOp,
+        left: &serde_json::Value,
+        right: &serde_json::Value,
+    ) -> Result<bool, RuleEngineError> {
+        use serde_json::Value;
+        match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(self.number(a) == self.number(b)),
+            (Value::Array(a), Value::Array(b)) => {
+                if a.len() != b.len() {
+                    return Ok(false);
+                }
+                for (a, b) in a.iter().zip(b) {
+                    if !self.values_equal(op, a, b)? {
                         return Ok(false);
                     }
                 }
                 Ok(true)
             }
-            Condition::Or(conditions) => {
-                for cond in conditions {
-                    if Self::evaluate_condition(cond, input, context, custom_functions)? {
+            (Value::Object(a), Value::Object(b)) => {
+                if a.len() != b.len() {
+                    return Ok(false);
+                }
+                for (key, a) in a {
+                    match b.get(key) {
+                        Some(b) if self.values_equal(op, a, b)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            (Value::Null, _) | (_, Value::Null) => Ok(left == right),
+            (a, b) if Self::same_type(a, b) => Ok(a == b),
+            _ if self.strict_comparisons => Err(Self::type_mismatch(op, left, right)),
+            (Value::Number(n), Value::String(s)) | (Value::String(s), Value::Number(n)) => {
+                Ok(Number::parse(s, self.decimal_arithmetic) == Some(self.number(n)))
+            }
+            (Value::Bool(b), Value::String(s)) | (Value::String(s), Value::Bool(b)) => {
+                Ok(s.parse::<bool>().ok() == Some(*b))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn number(&self, number: &serde_json::Number) -> Number {
+        Number::from_json(number, self.decimal_arithmetic)
+    }
+
+    fn same_type(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+        std::mem::discriminant(a) == std::mem::discriminant(b)
+    }
+
+    /// Tests whether `haystack` (an array, string or object) contains `needle`.
+    fn contains(
+        &self,
+        op: CompareOp,
+        haystack: &serde_json::Value,
+        needle: &serde_json::Value,
+    ) -> Result<bool, RuleEngineError> {
+        match (haystack, needle) {
+            (serde_json::Value::Array(items), needle) => {
+                for item in items {
+                    if self.values_equal(op, item, needle)? {
                         return Ok(true);
                     }
                 }
                 Ok(false)
             }
+            (serde_json::Value::Object(map), serde_json::Value::String(key)) => {
+                Ok(map.contains_key(key))
+            }
+            // Membership in a single number is almost certainly a mistake.
+            (serde_json::Value::Number(_), needle)
+                if matches!(op, CompareOp::In | CompareOp::NotIn) =>
+            {
+                Err(Self::type_mismatch(op, needle, haystack))
+            }
+            (haystack, needle) => match (Self::as_text(haystack), Self::as_text(needle)) {
+                (Some(haystack), Some(needle)) => Ok(haystack.contains(needle.as_ref())),
+                _ => Err(if matches!(op, CompareOp::In | CompareOp::NotIn) {
+                    Self::type_mismatch(op, needle, haystack)
+                } else {
+                    Self::type_mismatch(op, haystack, needle)
+                }),
+            },
+        }
+    }
+
+    /// Applies an arithmetic operator, see [`Number::apply`].
+    fn arithmetic(
+        &self,
+        op: ArithOp,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value<'static>, RuleEngineError> {
+        let (a, b) = match (self.operand(left), self.operand(right)) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => {
+                return Err(RuleEngineError::EvaluationError(format!(
+                    "Operator '{}' cannot be applied to {} and {}",
+                    op.as_str(),
+                    left.type_name(),
+                    right.type_name()
+                )));
+            }
+        };
+        if matches!(op, ArithOp::Div | ArithOp::Rem) && b.is_zero() {
+            return Err(RuleEngineError::EvaluationError(format!(
+                "Division by zero in '{} {} {}'",
+                left.to_json(),
+                op.as_str(),
+                right.to_json()
+            )));
+        }
+        Self::number_value(Number::apply(op, &a, &b, self.decimal_arithmetic))
+    }
+
+    /// Date-time and duration arithmetic: `datetime - datetime` gives a duration,
+    /// `datetime ± duration` a date-time, and durations can be added, subtracted
+    /// and scaled by numbers.
+    fn temporal_arithmetic(
+        op: ArithOp,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value<'static>, RuleEngineError> {
+        let mismatch = || Self::value_mismatch(op.as_str(), left, right);
+        let result = match op {
+            ArithOp::Add | ArithOp::Sub => {
+                match (Self::as_temporal(left), Self::as_temporal(right), op) {
+                    (Some(Value::DateTime(a)), Some(Value::Duration(d)), ArithOp::Add)
+                    | (Some(Value::Duration(d)), Some(Value::DateTime(a)), ArithOp::Add) => {
+                        a.checked_add_signed(d).map(Value::DateTime)
+                    }
+                    (Some(Value::DateTime(a)), Some(Value::Duration(d)), _) => {
+                        a.checked_sub_signed(d).map(Value::DateTime)
+                    }
+                    (Some(Value::DateTime(a)), Some(Value::DateTime(b)), ArithOp::Sub) => {
+                        Some(Value::Duration(a.signed_duration_since(b)))
+                    }
+                    (Some(Value::Duration(a)), Some(Value::Duration(b)), ArithOp::Add) => {
+                        a.checked_add(&b).map(Value::Duration)
+                    }
+                    (Some(Value::Duration(a)), Some(Value::Duration(b)), _) => {
+                        a.checked_sub(&b).map(Value::Duration)
+                    }
+                    _ => return Err(mismatch()),
+                }
+            }
+            ArithOp::Mul | ArithOp::Div => {
+                let (duration, factor) = match (left, right) {
+                    (Value::Duration(d), Value::Json(n)) => (d, n.as_f64()),
+                    (Value::Json(n), Value::Duration(d)) if op == ArithOp::Mul => (d, n.as_f64()),
+                    (Value::Duration(d), Value::Number(n)) => (d, Some(n.to_f64())),
+                    (Value::Number(n), Value::Duration(d)) if op == ArithOp::Mul => {
+                        (d, Some(n.to_f64()))
+                    }
+                    _ => return Err(mismatch()),
+                };
+                let factor = factor.ok_or_else(mismatch)?;
+                if op == ArithOp::Div && factor == 0.0 {
+                    return Err(RuleEngineError::EvaluationError(format!(
+                        "Division by zero in '{} / {}'",
+                        value::format_duration(*duration),
+                        factor
+                    )));
+                }
+                let millis = duration.num_milliseconds() as f64;
+                let scaled = if op == ArithOp::Mul {
+                    millis * factor
+                } else {
+                    millis / factor
+                };
+                TimeDelta::try_milliseconds(scaled.round() as i64).map(Value::Duration)
+            }
+            ArithOp::Rem => return Err(mismatch()),
+        };
+        result.ok_or_else(|| {
+            RuleEngineError::EvaluationError(format!(
+                "Operator '{}' overflowed the supported date range",
+                op.as_str()
+            ))
+        })
+    }
+
+    /// A date-time or duration, coercing strings (parsed as a date-time, then as a
+    /// duration) and numbers (seconds).
+    fn as_temporal(value: &Value) -> Option<Value<'static>> {
+        match value {
+            Value::DateTime(datetime) => Some(Value::DateTime(*datetime)),
+            Value::Duration(duration) => Some(Value::Duration(*duration)),
+            Value::Json(json) => match json.as_ref() {
+                serde_json::Value::String(s) => value::parse_datetime(s)
+                    .map(Value::DateTime)
+                    .or_else(|| value::parse_duration(s).map(Value::Duration)),
+                serde_json::Value::Number(_) => value.as_duration().map(Value::Duration),
+                _ => None,
+            },
+            Value::Number(_) => value.as_duration().map(Value::Duration),
+            Value::Version(_) | Value::Ip(_) | Value::Missing => None,
+        }
+    }
+
+    fn number_result(number: Number) -> Result<serde_json::Number, RuleEngineError> {
+        number.to_json().ok_or_else(|| {
+            RuleEngineError::EvaluationError(format!(
+                "Arithmetic produced a non-finite number ({:?})",
+                number
+            ))
+        })
+    }
+
+    /// The result of arithmetic. Decimals are kept as numbers rather than JSON,
+    /// which would round them to f64.
+    fn number_value(number: Number) -> Result<Value<'static>, RuleEngineError> {
+        if number.is_decimal() {
+            return Ok(Value::Number(number));
+        }
+        Self::number_result(number).map(|number| Value::owned(serde_json::Value::Number(number)))
+    }
+
+    /// An arithmetic operand, see [`RuleEngine::to_number`].
+    fn operand(&self, value: &Value) -> Result<Number, RuleEngineError> {
+        match value {
+            Value::Number(number) => Ok(number.clone()),
+            other => self.to_number(&other.to_json()),
+        }
+    }
+
+    /// A numeric operand: a number, or outside strict mode a numeric string.
+    fn to_number(&self, value: &serde_json::Value) -> Result<Number, RuleEngineError> {
+        match value {
+            serde_json::Value::Number(n) => Ok(self.number(n)),
+            serde_json::Value::String(s) if !self.strict_comparisons => {
+                Number::parse(s, self.decimal_arithmetic).ok_or_else(|| {
+                    RuleEngineError::EvaluationError(format!(
+                        "Cannot parse '{}' as number",
+                        s.trim()
+                    ))
+                })
+            }
+            other => Err(RuleEngineError::EvaluationError(format!(
+                "Expected a number, got {}",
+                Self::type_name(other)
+            ))),
+        }
+    }
+
+    /// The text of a string, or the decimal form of a number, for string operators.
+    fn as_text(value: &serde_json::Value) -> Option<Cow<'_, str>> {
+        match value {
+            serde_json::Value::String(s) => Some(Cow::Borrowed(s)),
+            serde_json::Value::Number(n) => Some(Cow::Owned(n.to_string())),
+            _ => None,
+        }
+    }
+
+    fn type_mismatch(
+        op: CompareOp,
+        left: &serde_json::Value,
+        right: &serde_json::Value,
+    ) -> RuleEngineError {
+        RuleEngineError::EvaluationError(format!(
+            "Operator '{}' cannot be applied to {} and {}",
+            op.as_str(),
+            Self::type_name(left),
+            Self::type_name(right)
+        ))
+    }
+
+    fn value_mismatch(op: &str, left: &Value, right: &Value) -> RuleEngineError {
+        RuleEngineError::EvaluationError(format!(
+            "Operator '{}' cannot be applied to {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        ))
+    }
+
+    fn type_name(value: &serde_json::Value) -> &'static str {
+        match value {
+            serde_json::Value::Null => "null",
+            serde_json::Value::Bool(_) => "boolean",
+            serde_json::Value::Number(_) => "number",
+            serde_json::Value::String(_) => "string",
+            serde_json::Value::Array(_) => "array",
+            serde_json::Value::Object(_) => "object",
+        }
+    }
+
+    /// A number or numeric string, as an operand of an ordering comparison.
+    fn as_number(&self, value: &serde_json::Value) -> Result<Number, RuleEngineError> {
+        match value {
+            serde_json::Value::Number(n) => Ok(self.number(n)),
+            serde_json::Value::String(s) => {
+                Number::parse(s, self.decimal_arithmetic).ok_or_else(|| {
+                    RuleEngineError::EvaluationError(format!("Cannot parse '{}' as number", s))
+                })
+            }
+            _ => Err(RuleEngineError::EvaluationError(
+                "Value must be a number or numeric string".to_string(),
+            )),
         }
     }
 }
@@ -0,0 +1,228 @@
+use crate::error::RuleEngineError;
+use crate::number::Number;
+use crate::parser::ArithOp;
+use serde_json::Value;
+
+/// A built-in function, given its arguments and whether the engine uses decimal
+/// arithmetic.
+pub(crate) type Builtin = fn(&[Value], bool) -> Result<Value, RuleEngineError>;
+
+/// Looks up a built-in function by name.
+pub(crate) fn builtin(name: &str) -> Option<Builtin> {
+    let function: Builtin = match name {
+        "length" => length,
+        "lower" => lower,
+        "upper" => upper,
+        "trim" => trim,
+        "abs" => abs,
+        "min" => min,
+        "max" => max,
+        "round" => round,
+        "sum" => sum,
+        "avg" => avg,
+        "count" => count,
+        "keys" => keys,
+        "coalesce" => coalesce,
+        "to_number" => to_number,
+        "to_string" => to_string,
+        _ => return None,
+    };
+    Some(function)
+}
+
+fn error(message: String) -> RuleEngineError {
+    RuleEngineError::EvaluationError(message)
+}
+
+fn one<'a>(name: &str, args: &'a [Value]) -> Result<&'a Value, RuleEngineError> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err(error(format!(
+            "Function '{}' expects 1 argument, got {}",
+            name,
+            args.len()
+        ))),
+    }
+}
+
+fn string_arg<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, RuleEngineError> {
+    one(name, args)?
+        .as_str()
+        .ok_or_else(|| error(format!("Function '{}' expects a string", name)))
+}
+
+/// Integral results are returned as integers, everything else as floats, or
+/// decimals in decimal mode.
+fn number(name: &str, value: Number) -> Result<Value, RuleEngineError> {
+    let value = match value {
+        Number::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Number::Int(f as i128),
+        other => other,
+    };
+    value.to_json().map(Value::Number).ok_or_else(|| {
+        error(format!(
+            "Function '{}' produced a result that is not a finite number",
+            name
+        ))
+    })
+}
+
+fn as_number(name: &str, value: &Value, decimal: bool) -> Result<Number, RuleEngineError> {
+    match value {
+        Value::Number(n) => Ok(Number::from_json(n, decimal)),
+        _ => Err(error(format!(
+            "Function '{}' expects numbers, got {}",
+            name, value
+        ))),
+    }
+}
+
+/// The numbers to aggregate: either the elements of a single array argument or
+/// the arguments themselves, so both `sum(prices)` and `sum(a, b, c)` work.
+fn numbers(name: &str, args: &[Value], decimal: bool) -> Result<Vec<Number>, RuleEngineError> {
+    let values = match args {
+        [Value::Array(items)] => items.as_slice(),
+        _ => args,
+    };
+    values
+        .iter()
+        .map(|value| as_number(name, value, decimal))
+        .collect()
+}
+
+fn total(values: &[Number], decimal: bool) -> Number {
+    values.iter().fold(Number::Int(0), |total, value| {
+        Number::apply(ArithOp::Add, &total, value, decimal)
+    })
+}
+
+fn length(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    match one("length", args)? {
+        Value::String(s) => Ok(Value::from(s.chars().count())),
+        Value::Array(items) => Ok(Value::from(items.len())),
+        Value::Object(map) => Ok(Value::from(map.len())),
+        other => Err(error(format!(
+            "Function 'length' expects a string, array or object, got {}",
+            other
+        ))),
+    }
+}
+
+fn lower(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    Ok(Value::from(string_arg("lower", args)?.to_lowercase()))
+}
+
+fn upper(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    Ok(Value::from(string_arg("upper", args)?.to_uppercase()))
+}
+
+fn trim(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    Ok(Value::from(string_arg("trim", args)?.trim()))
+}
+
+fn abs(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    let value = as_number("abs", one("abs", args)?, decimal)?;
+    if value < Number::Int(0) {
+        number("abs", value.negate())
+    } else {
+        number("abs", value)
+    }
+}
+
+fn min(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    let values = numbers("min", args, decimal)?;
+    match values.into_iter().reduce(|a, b| if b < a { b } else { a }) {
+        Some(min) => number("min", min),
+        None => Ok(Value::Null),
+    }
+}
+
+fn max(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    let values = numbers("max", args, decimal)?;
+    match values.into_iter().reduce(|a, b| if b > a { b } else { a }) {
+        Some(max) => number("max", max),
+        None => Ok(Value::Null),
+    }
+}
+
+/// `round(value)` rounds to an integer, `round(value, digits)` to `digits` decimals.
+fn round(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    let (value, digits) = match args {
+        [value] => (value, 0),
+        [value, digits] => (
+            value,
+            digits
+                .as_i64()
+                .ok_or_else(|| error("Function 'round' expects an integer digit count".into()))?,
+        ),
+        _ => {
+            return Err(error(format!(
+                "Function 'round' expects 1 or 2 arguments, got {}",
+                args.len()
+            )));
+        }
+    };
+    number("round", as_number("round", value, decimal)?.round(digits))
+}
+
+fn sum(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    number("sum", total(&numbers("sum", args, decimal)?, decimal))
+}
+
+fn avg(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    let values = numbers("avg", args, decimal)?;
+    if values.is_empty() {
+        return Ok(Value::Null);
+    }
+    let count = Number::Int(values.len() as i128);
+    number(
+        "avg",
+        Number::apply(ArithOp::Div, &total(&values, decimal), &count, decimal),
+    )
+}
+
+/// The number of elements in an array, or of arguments when not given an array.
+fn count(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    match args {
+        [Value::Array(items)] => Ok(Value::from(items.len())),
+        _ => Ok(Value::from(args.len())),
+    }
+}
+
+fn keys(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    match one("keys", args)? {
+        Value::Object(map) => Ok(Value::Array(map.keys().cloned().map(Value::from).collect())),
+        other => Err(error(format!(
+            "Function 'keys' expects an object, got {}",
+            other
+        ))),
+    }
+}
+
+/// The first argument that is not `null`.
+fn coalesce(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    Ok(args
+        .iter()
+        .find(|value| !value.is_null())
+        .cloned()
+        .unwrap_or(Value::Null))
+}
+
+fn to_number(args: &[Value], decimal: bool) -> Result<Value, RuleEngineError> {
+    match one("to_number", args)? {
+        Value::Number(n) => Ok(Value::Number(n.clone())),
+        Value::Bool(b) => Ok(Value::from(*b as i64)),
+        Value::String(s) => Number::parse(s, decimal)
+            .and_then(|number| number.to_json())
+            .map(Value::Number)
+            .ok_or_else(|| error(format!("Cannot convert '{}' to a number", s.trim()))),
+        other => Err(error(format!("Cannot convert {} to a number", other))),
+    }
+}
+
+/// Strings are returned unchanged; other values are serialized as JSON.
+fn to_string(args: &[Value], _decimal: bool) -> Result<Value, RuleEngineError> {
+    match one("to_string", args)? {
+        Value::String(s) => Ok(Value::from(s.clone())),
+        other => Ok(Value::from(other.to_string())),
+    }
+}
@@ -3,16 +3,24 @@
 //! This crate allows users to define rules in JSON and evaluate them against input data.
 //! It supports basic comparisons, nested conditions, and custom extensibility.
 
+mod compiled;
 mod config;
 mod engine;
 mod error;
+mod functions;
+mod network;
+mod number;
 pub mod parser;
+mod pattern;
+mod resolution;
 mod types;
+mod value;
 
 pub use config::Config;
-pub use engine::RuleEngine;
+pub use engine::{MissingKeyPolicy, RuleEngine};
 pub use error::RuleEngineError;
-pub use types::{Condition, Outcome, Rule};
+pub use resolution::ResolutionStrategy;
+pub use types::{Condition, Outcome, OutcomeType, Rule, RuleSet};
 
 #[cfg(test)]
 mod tests {
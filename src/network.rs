@@ -0,0 +1,94 @@
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+
+/// Parses an IP address (as a single-host network) or a CIDR block.
+pub(crate) fn parse_network(text: &str) -> Option<IpNet> {
+    let text = text.trim();
+    text.parse::<IpNet>()
+        .ok()
+        .or_else(|| text.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Formats single-host networks as a plain address and others in CIDR notation.
+pub(crate) fn format_network(network: &IpNet) -> String {
+    if network.prefix_len() == network.max_prefix_len() {
+        network.addr().to_string()
+    } else {
+        network.to_string()
+    }
+}
+
+/// A list of networks, compiled from a literal list such as
+/// `[10.0.0.0/8, 192.168.0.0/16]`. Networks are grouped by prefix length, so an
+/// address is looked up once per distinct prefix length however many networks
+/// the list holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CidrSet {
+    networks: Vec<IpNet>,
+    v4: BTreeMap<u8, HashSet<Ipv4Net>>,
+    v6: BTreeMap<u8, HashSet<Ipv6Net>>,
+}
+
+impl CidrSet {
+    pub(crate) fn new(networks: Vec<IpNet>) -> Self {
+        let mut v4: BTreeMap<u8, HashSet<Ipv4Net>> = BTreeMap::new();
+        let mut v6: BTreeMap<u8, HashSet<Ipv6Net>> = BTreeMap::new();
+        for network in &networks {
+            match network.trunc() {
+                IpNet::V4(network) => v4.entry(network.prefix_len()).or_default().insert(network),
+                IpNet::V6(network) => v6.entry(network.prefix_len()).or_default().insert(network),
+            };
+        }
+        CidrSet { networks, v4, v6 }
+    }
+
+    /// The networks in the order they were listed.
+    pub fn networks(&self) -> &[IpNet] {
+        &self.networks
+    }
+
+    /// Whether an address, or every address of a network, lies in one of the networks.
+    pub(crate) fn contains(&self, network: &IpNet) -> bool {
+        match network {
+            IpNet::V4(network) => self.v4.range(..=network.prefix_len()).any(|(len, blocks)| {
+                Ipv4Net::new(network.addr(), *len)
+                    .is_ok_and(|block| blocks.contains(&block.trunc()))
+            }),
+            IpNet::V6(network) => self.v6.range(..=network.prefix_len()).any(|(len, blocks)| {
+                Ipv6Net::new(network.addr(), *len)
+                    .is_ok_and(|block| blocks.contains(&block.trunc()))
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr_set_matches_by_prefix() {
+        let set = CidrSet::new(
+            [
+                "10.0.0.0/8",
+                "192.168.1.0/24",
+                "203.0.113.7",
+                "2001:db8::/32",
+            ]
+            .iter()
+            .map(|text| parse_network(text).unwrap())
+            .collect(),
+        );
+        let contains = |text: &str| set.contains(&parse_network(text).unwrap());
+        assert!(contains("10.20.30.40"));
+        assert!(contains("192.168.1.255"));
+        assert!(!contains("192.168.2.1"));
+        assert!(contains("203.0.113.7"));
+        assert!(!contains("203.0.113.8"));
+        assert!(contains("2001:db8:1::1"));
+        assert!(!contains("2001:db9::1"));
+        assert!(contains("10.1.0.0/16"));
+        assert!(!contains("192.168.0.0/16"));
+    }
+}
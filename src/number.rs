@@ -0,0 +1,281 @@
+use crate::parser::ArithOp;
+#[cfg(feature = "decimal")]
+use bigdecimal::{BigDecimal, ToPrimitive};
+use std::cmp::Ordering;
+
+/// A number as seen by comparisons and arithmetic. Integers (any `i64` or `u64`)
+/// are exact, and so are decimals in decimal mode; f64 is only used for floats
+/// outside decimal mode, or when an integer is mixed with one.
+#[derive(Debug, Clone)]
+pub(crate) enum Number {
+    Int(i128),
+    Float(f64),
+    #[cfg(feature = "decimal")]
+    Decimal(BigDecimal),
+}
+
+impl Number {
+    /// Reads a JSON number. Non-integers become decimals when `decimal` is set.
+    pub(crate) fn from_json(number: &serde_json::Number, decimal: bool) -> Self {
+        if let Some(i) = number.as_i64() {
+            Number::Int(i.into())
+        } else if let Some(u) = number.as_u64() {
+            Number::Int(u.into())
+        } else {
+            // The shortest form of the f64 is the decimal that was written, e.g. 0.1.
+            decimal
+                .then(|| Self::parse_decimal(&number.to_string()))
+                .flatten()
+                .unwrap_or_else(|| Number::Float(number.as_f64().unwrap_or(f64::NAN)))
+        }
+    }
+
+    /// Parses a numeric string, e.g. an operand given as `"42"`.
+    pub(crate) fn parse(text: &str, decimal: bool) -> Option<Self> {
+        let text = text.trim();
+        if let Ok(i) = text.parse::<i128>() {
+            return Some(Number::Int(i));
+        }
+        decimal
+            .then(|| Self::parse_decimal(text))
+            .flatten()
+            .or_else(|| text.parse::<f64>().ok().map(Number::Float))
+    }
+
+    #[cfg(feature = "decimal")]
+    fn parse_decimal(text: &str) -> Option<Self> {
+        text.parse::<BigDecimal>().ok().map(Number::Decimal)
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    fn parse_decimal(_text: &str) -> Option<Self> {
+        None
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(i) => *i == 0,
+            Number::Float(f) => *f == 0.0,
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) => d == &BigDecimal::from(0),
+        }
+    }
+
+    /// Whether the number is a decimal, which may have more digits than a JSON
+    /// number holds.
+    pub(crate) fn is_decimal(&self) -> bool {
+        match self {
+            #[cfg(feature = "decimal")]
+            Number::Decimal(_) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(i) => *i as f64,
+            Number::Float(f) => *f,
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    fn to_decimal(&self) -> Option<BigDecimal> {
+        match self {
+            Number::Int(i) => Some(BigDecimal::from(*i)),
+            Number::Float(f) => f.to_string().parse().ok(),
+            Number::Decimal(d) => Some(d.clone()),
+        }
+    }
+
+    /// Applies an arithmetic operator. Integers stay exact unless the result
+    /// overflows or a division leaves a remainder; the result is then a decimal in
+    /// decimal mode (or when an operand is one) and an f64 otherwise. Division by
+    /// zero must be ruled out by the caller.
+    pub(crate) fn apply(op: ArithOp, a: &Number, b: &Number, decimal: bool) -> Number {
+        if let (Number::Int(a), Number::Int(b)) = (a, b) {
+            let exact = match op {
+                ArithOp::Add => a.checked_add(*b),
+                ArithOp::Sub => a.checked_sub(*b),
+                ArithOp::Mul => a.checked_mul(*b),
+                ArithOp::Div if a.checked_rem(*b) == Some(0) => a.checked_div(*b),
+                ArithOp::Div => None,
+                ArithOp::Rem => a.checked_rem(*b),
+            };
+            if let Some(result) = exact {
+                return Number::Int(result);
+            }
+        }
+        if let Some(result) = Self::apply_decimal(op, a, b, decimal) {
+            return result;
+        }
+        let (a, b) = (a.to_f64(), b.to_f64());
+        Number::Float(match op {
+            ArithOp::Add => a + b,
+            ArithOp::Sub => a - b,
+            ArithOp::Mul => a * b,
+            ArithOp::Div => a / b,
+            ArithOp::Rem => a % b,
+        })
+    }
+
+    #[cfg(feature = "decimal")]
+    fn apply_decimal(op: ArithOp, a: &Number, b: &Number, decimal: bool) -> Option<Number> {
+        if !decimal && !matches!(a, Number::Decimal(_)) && !matches!(b, Number::Decimal(_)) {
+            return None;
+        }
+        let (a, b) = (a.to_decimal()?, b.to_decimal()?);
+        Some(Number::Decimal(match op {
+            ArithOp::Add => a + b,
+            ArithOp::Sub => a - b,
+            ArithOp::Mul => a * b,
+            ArithOp::Div => a / b,
+            ArithOp::Rem => a % b,
+        }))
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    fn apply_decimal(_op: ArithOp, _a: &Number, _b: &Number, _decimal: bool) -> Option<Number> {
+        None
+    }
+
+    pub(crate) fn negate(&self) -> Number {
+        match self {
+            Number::Int(i) => i
+                .checked_neg()
+                .map_or(Number::Float(-(*i as f64)), Number::Int),
+            Number::Float(f) => Number::Float(-f),
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) => Number::Decimal(-d),
+        }
+    }
+
+    /// Rounds to `digits` decimals, or to a multiple of `10^-digits` when `digits`
+    /// is negative. Halves round away from zero.
+    pub(crate) fn round(&self, digits: i64) -> Number {
+        match self {
+            Number::Int(i) if digits >= 0 => Number::Int(*i),
+            Number::Int(i) => {
+                let Some(factor) = u32::try_from(-digits)
+                    .ok()
+                    .and_then(|exponent| 10i128.checked_pow(exponent))
+                else {
+                    return Number::Int(0);
+                };
+                let remainder = i % factor;
+                let truncated = i - remainder;
+                if remainder.unsigned_abs() * 2 < factor.unsigned_abs() {
+                    Number::Int(truncated)
+                } else {
+                    truncated
+                        .checked_add(remainder.signum() * factor)
+                        .map_or_else(|| Number::Float(self.to_f64()).round(digits), Number::Int)
+                }
+            }
+            Number::Float(f) => {
+                let factor = 10f64.powi(digits.clamp(-308, 308) as i32);
+                Number::Float((f * factor).round() / factor)
+            }
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) => {
+                Number::Decimal(d.with_scale_round(digits, bigdecimal::RoundingMode::HalfUp))
+            }
+        }
+    }
+
+    /// Converts back to JSON. Integers outside the `i64` and `u64` ranges become
+    /// floats; `None` for infinities and NaN.
+    pub(crate) fn to_json(&self) -> Option<serde_json::Number> {
+        match self {
+            Number::Int(i) => i64::try_from(*i)
+                .map(serde_json::Number::from)
+                .or_else(|_| u64::try_from(*i).map(serde_json::Number::from))
+                .ok()
+                .or_else(|| serde_json::Number::from_f64(*i as f64)),
+            Number::Float(f) => serde_json::Number::from_f64(*f),
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) if d.is_integer() => match d.to_i128() {
+                Some(i) => Number::Int(i).to_json(),
+                None => d.to_string().parse().ok(),
+            },
+            #[cfg(feature = "decimal")]
+            Number::Decimal(d) => d.normalized().to_string().parse().ok(),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(b)),
+            #[cfg(feature = "decimal")]
+            (Number::Decimal(_), Number::Int(_) | Number::Decimal(_))
+            | (Number::Int(_), Number::Decimal(_)) => {
+                Some(self.to_decimal()?.cmp(&other.to_decimal()?))
+            }
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(text: &str) -> Number {
+        Number::parse(text, false).unwrap()
+    }
+
+    #[test]
+    fn test_integers_compare_exactly() {
+        assert!(number("9007199254740993") > number("9007199254740992"));
+        assert!(number("18446744073709551615") > number("9223372036854775807"));
+        assert!(number("-1") < number("18446744073709551615"));
+        assert!(number("3") == number("3.0"));
+    }
+
+    #[test]
+    fn test_integer_arithmetic_stays_exact() {
+        let sum = Number::apply(
+            ArithOp::Add,
+            &number("9223372036854775807"),
+            &number("1"),
+            false,
+        );
+        assert_eq!(
+            sum.to_json(),
+            Some(serde_json::Number::from(9223372036854775808u64))
+        );
+        let quotient = Number::apply(ArithOp::Div, &number("7"), &number("2"), false);
+        assert_eq!(quotient.to_json(), serde_json::Number::from_f64(3.5));
+    }
+
+    #[test]
+    fn test_round_integers_half_away_from_zero() {
+        let round = |text: &str, digits| number(text).round(digits).to_json().unwrap();
+        assert_eq!(round("9007199254740993", 2), 9007199254740993u64.into());
+        assert_eq!(round("125", -1), 130.into());
+        assert_eq!(round("-125", -1), (-130).into());
+        assert_eq!(round("124", -1), 120.into());
+        assert_eq!(round("124", -40), 0.into());
+        assert_eq!(round("2.5", 0), serde_json::Number::from_f64(3.0).unwrap());
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn test_decimal_arithmetic_is_exact() {
+        let decimal = |text: &str| Number::parse(text, true).unwrap();
+        let sum = Number::apply(ArithOp::Add, &decimal("0.1"), &decimal("0.2"), true);
+        assert!(sum == decimal("0.3"));
+        assert_eq!(sum.to_json().unwrap().to_string(), "0.3");
+        let third = Number::apply(ArithOp::Div, &decimal("1"), &decimal("3"), true);
+        assert!(Number::apply(ArithOp::Mul, &third, &decimal("3"), true) < decimal("1"));
+    }
+}
@@ -1,9 +1,1396 @@
+use crate::compiled;
 use crate::error::RuleEngineError;
-use crate::types::Rule;
+use crate::network;
+pub use crate::network::CidrSet;
+pub use crate::pattern::Pattern;
+use crate::types::{Condition, Rule, RuleSet};
+use crate::value;
+use chrono::{DateTime, TimeDelta, Utc};
+use ipnet::IpNet;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
 
+/// Parses rules from JSON, see [`parse_rule_set`]. A document that declares
+/// constants is rejected, since the rules would lose them; use
+/// [`parse_rule_set`] instead.
 pub fn parse_rules(json: &str) -> Result<Vec<Rule>, RuleEngineError> {
-    serde_json::from_str(json)
-        .map_err(|e| RuleEngineError::ParseError(format!("Failed to parse JSON: {}", e)))
+    parse_rule_set(json).and_then(into_rules)
+}
+
+/// The rules of a rule set that declares no constants.
+pub(crate) fn into_rules(rule_set: RuleSet) -> Result<Vec<Rule>, RuleEngineError> {
+    if !rule_set.constants.is_empty() {
+        let mut names: Vec<&str> = rule_set.constants.keys().map(String::as_str).collect();
+        names.sort();
+        return Err(RuleEngineError::ParseError(format!(
+            "The rules declare constants ({}); load them as a rule set to keep them",
+            names.join(", ")
+        )));
+    }
+    Ok(rule_set.rules)
+}
+
+/// Parses rules from JSON: either an array of rules or a document of the form
+/// `{"constants": {...}, "conditions": {"name": <condition>, ...}, "rules": [...]}`.
+/// Rules refer to the named conditions with `{"type": "Ref", "value": "name"}`.
+/// References are replaced by the conditions they name; unknown names and cycles
+/// are reported as a `ParseError`, as are syntax errors in condition and outcome
+/// expressions (including invalid date, IP and CIDR literals), invalid regular
+/// expressions, `between` bounds and templates, and outcomes combining `value`,
+/// `expr` and `type` in ways that cannot be evaluated.
+pub fn parse_rule_set(json: &str) -> Result<RuleSet, RuleEngineError> {
+    let parse_error =
+        |e: serde_json::Error| RuleEngineError::ParseError(format!("Failed to parse JSON: {}", e));
+    let (constants, definitions, rules) = if json.trim_start().starts_with('{') {
+        let document: RulesDocument = serde_json::from_str(json).map_err(parse_error)?;
+        (document.constants, document.conditions, document.rules)
+    } else {
+        (
+            HashMap::new(),
+            HashMap::new(),
+            serde_json::from_str(json).map_err(parse_error)?,
+        )
+    };
+    let mut resolver = RefResolver {
+        definitions: &definitions,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+    };
+    let mut names: Vec<&String> = definitions.keys().collect();
+    names.sort();
+    for name in names {
+        resolver.resolve_ref(name)?;
+    }
+    let rules = rules
+        .into_iter()
+        .map(|rule| {
+            let condition = resolver.resolve(&rule.condition)?;
+            compiled::check_condition(&condition)?;
+            rule.outcomes.iter().try_for_each(compiled::check_outcome)?;
+            Ok(Rule { condition, ..rule })
+        })
+        .collect::<Result<Vec<_>, RuleEngineError>>()?;
+    Ok(RuleSet { rules, constants })
+}
+
+#[derive(Deserialize)]
+struct RulesDocument {
+    #[serde(default)]
+    constants: HashMap<String, Value>,
+    #[serde(default)]
+    conditions: HashMap<String, Condition>,
+    rules: Vec<Rule>,
+}
+
+/// Inlines `Condition::Ref`s, resolving each named condition once.
+struct RefResolver<'a> {
+    definitions: &'a HashMap<String, Condition>,
+    resolved: HashMap<String, Condition>,
+    /// The names being resolved, to detect cycles.
+    stack: Vec<String>,
+}
+
+impl RefResolver<'_> {
+    fn resolve_ref(&mut self, name: &str) -> Result<Condition, RuleEngineError> {
+        if let Some(condition) = self.resolved.get(name) {
+            return Ok(condition.clone());
+        }
+        if let Some(start) = self.stack.iter().position(|entry| entry == name) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(RuleEngineError::ParseError(format!(
+                "Condition reference cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        let definition = self.definitions.get(name).ok_or_else(|| {
+            RuleEngineError::ParseError(format!("Unknown condition reference '{}'", name))
+        })?;
+        self.stack.push(name.to_string());
+        let condition = self.resolve(definition);
+        self.stack.pop();
+        let condition = condition?;
+        self.resolved.insert(name.to_string(), condition.clone());
+        Ok(condition)
+    }
+
+    fn resolve(&mut self, condition: &Condition) -> Result<Condition, RuleEngineError> {
+        let mut resolve_all = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(|condition| self.resolve(condition))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match condition {
+            Condition::Simple(_) => condition.clone(),
+            Condition::And(conditions) => Condition::And(resolve_all(conditions)?),
+            Condition::Or(conditions) => Condition::Or(resolve_all(conditions)?),
+            Condition::Xor(conditions) => Condition::Xor(resolve_all(conditions)?),
+            Condition::AtLeast { n, conditions } => Condition::AtLeast {
+                n: *n,
+                conditions: resolve_all(conditions)?,
+            },
+            Condition::AtMost { n, conditions } => Condition::AtMost {
+                n: *n,
+                conditions: resolve_all(conditions)?,
+            },
+            Condition::Not(condition) => Condition::Not(Box::new(self.resolve(condition)?)),
+            Condition::Any {
+                field,
+                binding,
+                condition,
+            } => Condition::Any {
+                field: field.clone(),
+                binding: binding.clone(),
+                condition: Box::new(self.resolve(condition)?),
+            },
+            Condition::All {
+                field,
+                binding,
+                condition,
+            } => Condition::All {
+                field: field.clone(),
+                binding: binding.clone(),
+                condition: Box::new(self.resolve(condition)?),
+            },
+            Condition::None {
+                field,
+                binding,
+                condition,
+            } => Condition::None {
+                field: field.clone(),
+                binding: binding.clone(),
+                condition: Box::new(self.resolve(condition)?),
+            },
+            Condition::Ref(name) => self.resolve_ref(name)?,
+        })
+    }
+}
+
+/// A parsed `Condition::Simple` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A quoted string, number, boolean or `null`.
+    Literal(Value),
+    /// A date or date-time literal such as `2024-01-01` or `2024-01-01T09:30:00Z`.
+    DateTime(DateTime<Utc>),
+    /// A duration literal such as `30m`, `7d` or `1h30m`.
+    Duration(TimeDelta),
+    /// A semantic version literal such as `2.3.0` or `1.0.0-beta.2`.
+    Version(semver::Version),
+    /// An IP address such as `10.0.0.1` or a CIDR block such as `10.0.0.0/8` or
+    /// `2001:db8::/32`. An address is a network of a single host.
+    Ip(IpNet),
+    /// A list of networks compiled for fast lookup when rules are loaded.
+    CidrSet(CidrSet),
+    /// A regular expression compiled from a literal pattern when rules are loaded.
+    Pattern(Pattern),
+    /// A field reference, looked up in the input and then the context.
+    Field(FieldPath),
+    /// A constant such as `$min_age`, declared by the rules document or the engine.
+    Constant(String),
+    /// A bare identifier on the right-hand side of a comparison, such as `active`
+    /// in `status = active`. Compiled to a string, or to a field reference with
+    /// `RuleEngine::with_bareword_fields`.
+    Bareword(String),
+    /// A list such as `["US", "CA"]`.
+    List(Vec<Expr>),
+    /// A custom function call such as `length(name)`.
+    Call { name: String, args: Vec<Expr> },
+    /// Arithmetic on two numeric operands, e.g. `price * quantity`.
+    Arithmetic {
+        op: ArithOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// Unary minus.
+    Negate(Box<Expr>),
+    /// A comparison between two operands.
+    Compare {
+        op: CompareOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// A range test: `age between 18 and 65` (inclusive) or `age between [18, 65)`,
+    /// where `[`/`]` include a bound and `(`/`)` exclude it.
+    Between {
+        value: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        low_inclusive: bool,
+        high_inclusive: bool,
+    },
+    /// `left ?? right`: `right` when `left` is missing or `null`, otherwise `left`.
+    Coalesce { left: Box<Expr>, right: Box<Expr> },
+    /// A postfix test such as `nickname exists`. It never fails on a missing field.
+    Check { op: CheckOp, operand: Box<Expr> },
+}
+
+/// A reference to a possibly nested field: `user.address.country`, `items[0].sku`
+/// or the JSON Pointer `/items/0/sku`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldPath {
+    pub segments: Vec<PathSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    /// An object key. When applied to an array a numeric key selects an index,
+    /// following JSON Pointer semantics.
+    Key(String),
+    Index(usize),
+}
+
+impl FieldPath {
+    /// A path naming a single top-level field.
+    pub fn key(name: &str) -> Self {
+        FieldPath {
+            segments: vec![PathSegment::Key(name.to_string())],
+        }
+    }
+
+    /// Returns the field name if this path is a single plain identifier.
+    pub fn as_identifier(&self) -> Option<&str> {
+        match self.segments.as_slice() {
+            [PathSegment::Key(name)] if is_identifier(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Walks the segments after the first one into `root`.
+    pub fn resolve_in<'a>(&self, root: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .skip(1)
+            .try_fold(root, |value, segment| match (value, segment) {
+                (Value::Object(map), PathSegment::Key(key)) => map.get(key),
+                (Value::Object(map), PathSegment::Index(index)) => map.get(&index.to_string()),
+                (Value::Array(items), PathSegment::Key(key)) => {
+                    key.parse::<usize>().ok().and_then(|index| items.get(index))
+                }
+                (Value::Array(items), PathSegment::Index(index)) => items.get(*index),
+                _ => None,
+            })
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) if is_identifier(key) => {
+                    if i > 0 {
+                        write!(f, ".")?;
+                    }
+                    write!(f, "{}", key)?;
+                }
+                PathSegment::Key(key) => write!(f, "[{:?}]", key)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Arithmetic operators, in the usual precedence: `*`, `/` and `%` bind tighter
+/// than `+` and `-`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl ArithOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+            ArithOp::Rem => "%",
+        }
+    }
+}
+
+/// The comparison operators understood by simple conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+    In,
+    NotIn,
+    Contains,
+    StartsWith,
+    EndsWith,
+    /// True when a date-time lies within a duration of the current time.
+    Within,
+    /// True when a regular expression matches somewhere in the string.
+    Matches,
+    NotMatches,
+    /// True when an IP address lies in a CIDR block or a list of them.
+    InCidr,
+    /// True when a version satisfies a requirement such as `"^2.3"` or `">=1.2, <2"`.
+    Satisfies,
+}
+
+impl CompareOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Gt => ">",
+            CompareOp::Lt => "<",
+            CompareOp::Ge => ">=",
+            CompareOp::Le => "<=",
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::In => "in",
+            CompareOp::NotIn => "not in",
+            CompareOp::Contains => "contains",
+            CompareOp::StartsWith => "starts_with",
+            CompareOp::EndsWith => "ends_with",
+            CompareOp::Within => "within",
+            CompareOp::Matches => "matches",
+            CompareOp::NotMatches => "not matches",
+            CompareOp::InCidr => "in_cidr",
+            CompareOp::Satisfies => "satisfies",
+        }
+    }
+}
+
+/// Postfix tests on a single operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOp {
+    /// The field is present, even if `null`.
+    Exists,
+    /// The field is missing or `null`.
+    IsNull,
+    /// The field is missing, `null`, or an empty string, array or object.
+    IsEmpty,
+}
+
+impl CheckOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckOp::Exists => "exists",
+            CheckOp::IsNull => "is_null",
+            CheckOp::IsEmpty => "is_empty",
+        }
+    }
+}
+
+/// Parses a simple condition expression such as `name = "John Smith"` or `length(name) > 3`.
+pub fn parse_expression(input: &str) -> Result<Expr, RuleEngineError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        source: input,
+        tokens,
+        pos: 0,
+    };
+    let expr = parser.parse_comparison()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(parser.error_at(token.offset, &format!("Unexpected {}", token.kind))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Constant(String),
+    Pointer(Vec<String>),
+    Number(serde_json::Number),
+    DateTime(DateTime<Utc>),
+    Duration(TimeDelta),
+    Version(semver::Version),
+    Ip(IpNet),
+    Str(String),
+    True,
+    False,
+    Null,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Dot,
+    Comma,
+    Op(CompareOp),
+    Arith(ArithOp),
+    Coalesce,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(name) => write!(f, "identifier '{}'", name),
+            TokenKind::Constant(name) => write!(f, "constant '${}'", name),
+            TokenKind::Pointer(segments) => write!(f, "pointer '/{}'", segments.join("/")),
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::DateTime(datetime) => write!(f, "date {}", datetime.to_rfc3339()),
+            TokenKind::Duration(duration) => {
+                write!(f, "duration {}", value::format_duration(*duration))
+            }
+            TokenKind::Version(version) => write!(f, "version {}", version),
+            TokenKind::Ip(network) => write!(f, "address {}", network::format_network(network)),
+            TokenKind::Str(s) => write!(f, "string \"{}\"", s),
+            TokenKind::True => write!(f, "'true'"),
+            TokenKind::False => write!(f, "'false'"),
+            TokenKind::Null => write!(f, "'null'"),
+            TokenKind::LParen => write!(f, "'('"),
+            TokenKind::RParen => write!(f, "')'"),
+            TokenKind::LBracket => write!(f, "'['"),
+            TokenKind::RBracket => write!(f, "']'"),
+            TokenKind::Dot => write!(f, "'.'"),
+            TokenKind::Comma => write!(f, "','"),
+            TokenKind::Op(op) => write!(f, "operator '{}'", op.as_str()),
+            TokenKind::Arith(op) => write!(f, "operator '{}'", op.as_str()),
+            TokenKind::Coalesce => write!(f, "operator '??'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Character offset of the token in the source expression.
+    offset: usize,
+}
+
+fn syntax_error(source: &str, offset: usize, message: &str) -> RuleEngineError {
+    RuleEngineError::ParseError(format!(
+        "{} at position {} in '{}'",
+        message, offset, source
+    ))
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, RuleEngineError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+            '[' => {
+                i += 1;
+                TokenKind::LBracket
+            }
+            ']' => {
+                i += 1;
+                TokenKind::RBracket
+            }
+            ',' => {
+                i += 1;
+                TokenKind::Comma
+            }
+            '.' => {
+                i += 1;
+                TokenKind::Dot
+            }
+            // A `/` where an operand is expected starts a JSON pointer, otherwise it divides.
+            '/' if expects_operand(tokens.last()) => {
+                let (segments, end) = lex_pointer(source, &chars, i)?;
+                i = end;
+                TokenKind::Pointer(segments)
+            }
+            '?' if chars.get(i + 1) == Some(&'?') => {
+                i += 2;
+                TokenKind::Coalesce
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                i += 1;
+                TokenKind::Arith(match c {
+                    '+' => ArithOp::Add,
+                    '-' => ArithOp::Sub,
+                    '*' => ArithOp::Mul,
+                    '/' => ArithOp::Div,
+                    _ => ArithOp::Rem,
+                })
+            }
+            // A path segment such as the `0` in `items.0.sku` is always an index.
+            c if c.is_ascii_digit()
+                && matches!(
+                    tokens.last(),
+                    Some(Token {
+                        kind: TokenKind::Dot,
+                        ..
+                    })
+                ) =>
+            {
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                let index = digits.parse::<u64>().map_err(|_| {
+                    syntax_error(source, start, &format!("Invalid index '{}'", digits))
+                })?;
+                TokenKind::Number(serde_json::Number::from(index))
+            }
+            '>' | '<' => {
+                i += 1;
+                let or_equal = chars.get(i) == Some(&'=');
+                if or_equal {
+                    i += 1;
+                }
+                TokenKind::Op(match (c, or_equal) {
+                    ('>', false) => CompareOp::Gt,
+                    ('>', true) => CompareOp::Ge,
+                    ('<', false) => CompareOp::Lt,
+                    _ => CompareOp::Le,
+                })
+            }
+            '=' => {
+                i += 1;
+                // `==` is accepted as an alias for `=`.
+                if chars.get(i) == Some(&'=') {
+                    i += 1;
+                }
+                TokenKind::Op(CompareOp::Eq)
+            }
+            '!' if chars.get(i + 1) == Some(&'=') => {
+                i += 2;
+                TokenKind::Op(CompareOp::Ne)
+            }
+            '$' => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                if i == start + 1 {
+                    return Err(syntax_error(
+                        source,
+                        start,
+                        "Expected a constant name after '$'",
+                    ));
+                }
+                TokenKind::Constant(chars[start + 1..i].iter().collect())
+            }
+            '"' | '\'' => {
+                let (value, end) = lex_string(source, &chars, i)?;
+                i = end;
+                TokenKind::Str(value)
+            }
+            c if (c.is_ascii_hexdigit() || c == ':') && ip_literal_end(&chars, i).is_some() => {
+                let end = ip_literal_end(&chars, i).unwrap();
+                let text: String = chars[start..end].iter().collect();
+                let network = network::parse_network(&text).ok_or_else(|| {
+                    syntax_error(
+                        source,
+                        start,
+                        &format!("Invalid IP address literal '{}'", text),
+                    )
+                })?;
+                if network.trunc() != network {
+                    return Err(syntax_error(
+                        source,
+                        start,
+                        &format!("Invalid CIDR literal '{}': host bits are set", text),
+                    ));
+                }
+                i = end;
+                TokenKind::Ip(network)
+            }
+            c if c.is_ascii_digit() && is_date_literal(&chars, i) => {
+                let (datetime, end) = lex_datetime(source, &chars, i)?;
+                i = end;
+                TokenKind::DateTime(datetime)
+            }
+            c if c.is_ascii_digit() && version_literal_end(&chars, i).is_some() => {
+                let end = version_literal_end(&chars, i).unwrap();
+                let text: String = chars[start..end].iter().collect();
+                let version = semver::Version::parse(&text).map_err(|e| {
+                    syntax_error(
+                        source,
+                        start,
+                        &format!("Invalid version literal '{}': {}", text, e),
+                    )
+                })?;
+                i = end;
+                TokenKind::Version(version)
+            }
+            c if c.is_ascii_digit() && is_duration_literal(&chars, i) => {
+                while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let duration = value::parse_duration(&text).ok_or_else(|| {
+                    syntax_error(
+                        source,
+                        start,
+                        &format!("Invalid duration literal '{}'", text),
+                    )
+                })?;
+                TokenKind::Duration(duration)
+            }
+            c if c.is_ascii_digit() => {
+                let (number, end) = lex_number(source, &chars, i)?;
+                i = end;
+                TokenKind::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.as_str() {
+                    "true" => TokenKind::True,
+                    "false" => TokenKind::False,
+                    "null" => TokenKind::Null,
+                    _ => TokenKind::Ident(word),
+                }
+            }
+            other => {
+                return Err(syntax_error(
+                    source,
+                    start,
+                    &format!("Unexpected character '{}'", other),
+                ));
+            }
+        };
+        tokens.push(Token {
+            kind,
+            offset: start,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Words that act as operators and are therefore followed by an operand.
+const KEYWORD_OPERATORS: &[&str] = &[
+    "in",
+    "not",
+    "contains",
+    "starts_with",
+    "ends_with",
+    "within",
+    "matches",
+    "satisfies",
+    "in_cidr",
+    "between",
+    "and",
+];
+
+/// Whether the token after `previous` starts an operand rather than continuing one.
+fn expects_operand(previous: Option<&Token>) -> bool {
+    match previous.map(|t| &t.kind) {
+        None => true,
+        Some(TokenKind::Ident(word)) => KEYWORD_OPERATORS.contains(&word.as_str()),
+        Some(
+            TokenKind::Op(_)
+            | TokenKind::Arith(_)
+            | TokenKind::Coalesce
+            | TokenKind::LParen
+            | TokenKind::LBracket
+            | TokenKind::Comma,
+        ) => true,
+        Some(_) => false,
+    }
+}
+
+/// Whether a `YYYY-MM-DD` date starts at `start`.
+fn is_date_literal(chars: &[char], start: usize) -> bool {
+    let pattern = "dddd-dd-dd";
+    chars.len() >= start + pattern.len()
+        && pattern.chars().enumerate().all(|(offset, p)| {
+            let c = chars[start + offset];
+            if p == 'd' { c.is_ascii_digit() } else { c == p }
+        })
+        && !chars
+            .get(start + pattern.len())
+            .is_some_and(|c| c.is_ascii_digit())
+}
+
+/// The end of an IP address or CIDR literal such as `10.0.0.0/8` or
+/// `2001:db8::/32` starting at `start`: four dotted decimal octets, or hex groups
+/// with at least two colons, optionally followed by a prefix length.
+fn ip_literal_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    while chars
+        .get(i)
+        .is_some_and(|c| c.is_ascii_hexdigit() || *c == ':' || *c == '.')
+    {
+        i += 1;
+    }
+    let text: String = chars[start..i].iter().collect();
+    let is_v4 = text.split('.').count() == 4
+        && text
+            .split('.')
+            .all(|octet| !octet.is_empty() && octet.chars().all(|c| c.is_ascii_digit()));
+    if !is_v4 && text.matches(':').count() < 2 {
+        return None;
+    }
+    if chars.get(i) == Some(&'/') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+        i += 1;
+        while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+            i += 1;
+        }
+    }
+    Some(i)
+}
+
+/// The end of a version literal such as `2.3.0` or `1.0.0-rc.1` starting at
+/// `start`: three numeric components, then optional pre-release and build parts.
+fn version_literal_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    for component in 0..3 {
+        if component > 0 {
+            if chars.get(i) != Some(&'.') {
+                return None;
+            }
+            i += 1;
+        }
+        let digits = i;
+        while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+            i += 1;
+        }
+        if i == digits {
+            return None;
+        }
+    }
+    // A fourth component makes it an IP address rather than a version.
+    if chars.get(i) == Some(&'.') {
+        return None;
+    }
+    for marker in ['-', '+'] {
+        if chars.get(i) == Some(&marker)
+            && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            i += 1;
+            while chars
+                .get(i)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+            {
+                i += 1;
+            }
+        }
+    }
+    Some(i)
+}
+
+/// Whether the digits at `start` are followed by a duration unit, as in `30m`.
+fn is_duration_literal(chars: &[char], start: usize) -> bool {
+    let mut i = start;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    chars
+        .get(i)
+        .is_some_and(|c| matches!(c, 'w' | 'd' | 'h' | 'm' | 's'))
+}
+
+/// Lexes a date with an optional RFC 3339 time part, e.g. `2024-01-01T09:30:00+02:00`.
+fn lex_datetime(
+    source: &str,
+    chars: &[char],
+    start: usize,
+) -> Result<(DateTime<Utc>, usize), RuleEngineError> {
+    let mut i = start + 10;
+    if matches!(chars.get(i), Some('T') | Some('t'))
+        && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+    {
+        i += 1;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == ':' || chars[i] == '.') {
+            i += 1;
+        }
+        match chars.get(i) {
+            Some('Z') | Some('z') => i += 1,
+            Some('+') | Some('-')
+                if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+                    && chars.get(i + 3) == Some(&':') =>
+            {
+                i += 6;
+            }
+            _ => {}
+        }
+    }
+    let text: String = chars[start..i.min(chars.len())].iter().collect();
+    value::parse_datetime(&text)
+        .map(|datetime| (datetime, i))
+        .ok_or_else(|| syntax_error(source, start, &format!("Invalid date literal '{}'", text)))
+}
+
+fn lex_string(
+    source: &str,
+    chars: &[char],
+    start: usize,
+) -> Result<(String, usize), RuleEngineError> {
+    let quote = chars[start];
+    let mut value = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Ok((value, i + 1)),
+            '\\' => {
+                let escaped = chars.get(i + 1).ok_or_else(|| {
+                    syntax_error(source, i, "Unterminated escape sequence in string literal")
+                })?;
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '\\' | '"' | '\'' => *escaped,
+                    other => {
+                        return Err(syntax_error(
+                            source,
+                            i,
+                            &format!("Unknown escape sequence '\\{}'", other),
+                        ));
+                    }
+                });
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(syntax_error(source, start, "Unterminated string literal"))
+}
+
+/// Lexes a JSON Pointer (RFC 6901) such as `/items/0/sku`, decoding `~0` and `~1`.
+fn lex_pointer(
+    source: &str,
+    chars: &[char],
+    start: usize,
+) -> Result<(Vec<String>, usize), RuleEngineError> {
+    let mut segments = Vec::new();
+    let mut i = start;
+    while chars.get(i) == Some(&'/') {
+        i += 1;
+        let mut segment = String::new();
+        while let Some(&c) = chars.get(i) {
+            if c.is_whitespace() || "/(),[]<>=!+*%".contains(c) {
+                break;
+            }
+            if c == '~' {
+                segment.push(match chars.get(i + 1) {
+                    Some('0') => '~',
+                    Some('1') => '/',
+                    _ => {
+                        return Err(syntax_error(
+                            source,
+                            i,
+                            "Invalid escape in JSON pointer, expected '~0' or '~1'",
+                        ));
+                    }
+                });
+                i += 2;
+            } else {
+                segment.push(c);
+                i += 1;
+            }
+        }
+        segments.push(segment);
+    }
+    if segments.first().is_none_or(|first| first.is_empty()) {
+        return Err(syntax_error(
+            source,
+            start,
+            "JSON pointer must start with a field name",
+        ));
+    }
+    Ok((segments, i))
+}
+
+fn lex_number(
+    source: &str,
+    chars: &[char],
+    start: usize,
+) -> Result<(serde_json::Number, usize), RuleEngineError> {
+    let mut i = start;
+    let mut is_float = false;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+        is_float = true;
+        i += 1;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    if matches!(chars.get(i), Some('e') | Some('E')) {
+        let mut j = i + 1;
+        if matches!(chars.get(j), Some('+') | Some('-')) {
+            j += 1;
+        }
+        if chars.get(j).is_some_and(|c| c.is_ascii_digit()) {
+            is_float = true;
+            i = j;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+    if chars.get(i).is_some_and(|c| c.is_alphabetic() || *c == '_') {
+        return Err(syntax_error(source, start, "Invalid number literal"));
+    }
+
+    let text: String = chars[start..i].iter().collect();
+    let number = if is_float {
+        None
+    } else if let Ok(n) = text.parse::<i64>() {
+        Some(serde_json::Number::from(n))
+    } else {
+        text.parse::<u64>().ok().map(serde_json::Number::from)
+    };
+    let number = match number {
+        Some(n) => n,
+        None => text
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .ok_or_else(|| {
+                syntax_error(source, start, &format!("Invalid number literal '{}'", text))
+            })?,
+    };
+    Ok((number, i))
+}
+
+/// Negates a numeric literal, keeping integers exact.
+fn negate_number(n: &serde_json::Number) -> Option<serde_json::Number> {
+    if let Some(i) = n.as_i64() {
+        return i.checked_neg().map(serde_json::Number::from);
+    }
+    if n.as_u64() == Some(1 << 63) {
+        return Some(serde_json::Number::from(i64::MIN));
+    }
+    n.as_f64().and_then(|f| serde_json::Number::from_f64(-f))
+}
+
+fn into_bareword(expr: Expr) -> Expr {
+    match expr {
+        Expr::Field(path) => match path.as_identifier() {
+            Some(word) => Expr::Bareword(word.to_string()),
+            None => Expr::Field(path),
+        },
+        Expr::List(items) => Expr::List(items.into_iter().map(into_bareword).collect()),
+        other => other,
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn end_offset(&self) -> usize {
+        self.source.chars().count()
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> RuleEngineError {
+        syntax_error(self.source, offset, message)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), RuleEngineError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(()),
+            Some(token) => Err(self.error_at(
+                token.offset,
+                &format!("Expected {} but found {}", kind, token.kind),
+            )),
+            None => Err(self.error_at(
+                self.end_offset(),
+                &format!("Expected {} but reached end of expression", kind),
+            )),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, RuleEngineError> {
+        let left = self.parse_coalesce()?;
+        if let Some(op) = self.parse_check() {
+            return Ok(Expr::Check {
+                op,
+                operand: Box::new(left),
+            });
+        }
+        if self.peek_keyword("between") {
+            self.pos += 1;
+            return self.parse_between(left);
+        }
+        if let Some(op) = self.parse_operator()? {
+            let right = into_bareword(self.parse_coalesce()?);
+            return Ok(Expr::Compare {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            });
+        }
+        Ok(left)
+    }
+
+    /// Parses the bounds after `between`: `low and high`, or an interval such as
+    /// `[low, high)`.
+    fn parse_between(&mut self, value: Expr) -> Result<Expr, RuleEngineError> {
+        let low_inclusive = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::LBracket) => Some(true),
+            Some(TokenKind::LParen) => Some(false),
+            _ => None,
+        };
+        let (low, high, low_inclusive, high_inclusive) = match low_inclusive {
+            Some(low_inclusive) => {
+                self.pos += 1;
+                let low = into_bareword(self.parse_coalesce()?);
+                self.expect(TokenKind::Comma)?;
+                let high = into_bareword(self.parse_coalesce()?);
+                let high_inclusive = match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RBracket,
+                        ..
+                    }) => true,
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => false,
+                    Some(token) => {
+                        return Err(self.error_at(
+                            token.offset,
+                            &format!("Expected ']' or ')' but found {}", token.kind),
+                        ));
+                    }
+                    None => {
+                        return Err(self.error_at(
+                            self.end_offset(),
+                            "Expected ']' or ')' but reached end of expression",
+                        ));
+                    }
+                };
+                (low, high, low_inclusive, high_inclusive)
+            }
+            None => {
+                let low = into_bareword(self.parse_coalesce()?);
+                if !self.peek_keyword("and") {
+                    let offset = self.peek().map_or(self.end_offset(), |t| t.offset);
+                    return Err(self.error_at(offset, "Expected 'and' after the lower bound"));
+                }
+                self.pos += 1;
+                let high = into_bareword(self.parse_coalesce()?);
+                (low, high, true, true)
+            }
+        };
+        Ok(Expr::Between {
+            value: Box::new(value),
+            low: Box::new(low),
+            high: Box::new(high),
+            low_inclusive,
+            high_inclusive,
+        })
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Ident(word)) if word == keyword)
+    }
+
+    /// `??` binds looser than arithmetic but tighter than comparisons, so
+    /// `nickname ?? name = "Haile"` compares the coalesced value.
+    fn parse_coalesce(&mut self) -> Result<Expr, RuleEngineError> {
+        let mut left = self.parse_additive()?;
+        while matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Coalesce)) {
+            self.pos += 1;
+            let right = self.parse_additive()?;
+            left = Expr::Coalesce {
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, RuleEngineError> {
+        let mut left = self.parse_multiplicative()?;
+        while let Some(op) = self.peek_arith(&[ArithOp::Add, ArithOp::Sub]) {
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = Expr::Arithmetic {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, RuleEngineError> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_arith(&[ArithOp::Mul, ArithOp::Div, ArithOp::Rem]) {
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = Expr::Arithmetic {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, RuleEngineError> {
+        if self.peek_arith(&[ArithOp::Sub]).is_some() {
+            self.pos += 1;
+            return Ok(match self.parse_unary()? {
+                Expr::Literal(Value::Number(n)) => match negate_number(&n) {
+                    Some(negated) => Expr::Literal(Value::Number(negated)),
+                    None => Expr::Negate(Box::new(Expr::Literal(Value::Number(n)))),
+                },
+                operand => Expr::Negate(Box::new(operand)),
+            });
+        }
+        self.parse_operand()
+    }
+
+    fn peek_arith(&self, ops: &[ArithOp]) -> Option<ArithOp> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Arith(op)) if ops.contains(op) => Some(*op),
+            _ => None,
+        }
+    }
+
+    /// Consumes a postfix test (`exists`, `is_null`, `is_empty`) if one follows.
+    fn parse_check(&mut self) -> Option<CheckOp> {
+        let op = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Ident(word)) => match word.as_str() {
+                "exists" => CheckOp::Exists,
+                "is_null" => CheckOp::IsNull,
+                "is_empty" => CheckOp::IsEmpty,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(op)
+    }
+
+    /// Consumes a symbolic (`>=`) or keyword (`not in`) operator if one follows.
+    fn parse_operator(&mut self) -> Result<Option<CompareOp>, RuleEngineError> {
+        let (op, width) = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Op(op)) => (*op, 1),
+            Some(TokenKind::Ident(word)) => match word.as_str() {
+                "in" => (CompareOp::In, 1),
+                "contains" => (CompareOp::Contains, 1),
+                "starts_with" => (CompareOp::StartsWith, 1),
+                "ends_with" => (CompareOp::EndsWith, 1),
+                "within" => (CompareOp::Within, 1),
+                "matches" => (CompareOp::Matches, 1),
+                "satisfies" => (CompareOp::Satisfies, 1),
+                "in_cidr" => (CompareOp::InCidr, 1),
+                "not" => match self.tokens.get(self.pos + 1) {
+                    Some(Token {
+                        kind: TokenKind::Ident(next),
+                        ..
+                    }) if next == "in" => (CompareOp::NotIn, 2),
+                    Some(Token {
+                        kind: TokenKind::Ident(next),
+                        ..
+                    }) if next == "matches" => (CompareOp::NotMatches, 2),
+                    _ => {
+                        let offset = self.tokens[self.pos].offset;
+                        return Err(self.error_at(offset, "Expected 'in' or 'matches' after 'not'"));
+                    }
+                },
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += width;
+        Ok(Some(op))
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr, RuleEngineError> {
+        let token = self.next().ok_or_else(|| {
+            self.error_at(
+                self.end_offset(),
+                "Expected an operand but reached end of expression",
+            )
+        })?;
+        match token.kind {
+            TokenKind::Number(n) => Ok(Expr::Literal(Value::Number(n))),
+            TokenKind::DateTime(datetime) => Ok(Expr::DateTime(datetime)),
+            TokenKind::Duration(duration) => Ok(Expr::Duration(duration)),
+            TokenKind::Version(version) => Ok(Expr::Version(version)),
+            TokenKind::Ip(network) => Ok(Expr::Ip(network)),
+            TokenKind::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            TokenKind::True => Ok(Expr::Literal(Value::Bool(true))),
+            TokenKind::False => Ok(Expr::Literal(Value::Bool(false))),
+            TokenKind::Null => Ok(Expr::Literal(Value::Null)),
+            TokenKind::LParen => {
+                let inner = self.parse_comparison()?;
+                self.expect(TokenKind::RParen)?;
+                Ok(inner)
+            }
+            TokenKind::LBracket => self.parse_list(token.offset),
+            TokenKind::Ident(name) => {
+                if matches!(self.peek(), Some(t) if t.kind == TokenKind::LParen) {
+                    self.pos += 1;
+                    let args = self.parse_arguments()?;
+                    Ok(Expr::Call { name, args })
+                } else {
+                    self.parse_path(name).map(Expr::Field)
+                }
+            }
+            TokenKind::Constant(name) => Ok(Expr::Constant(name)),
+            TokenKind::Pointer(segments) => Ok(Expr::Field(FieldPath {
+                segments: segments.into_iter().map(PathSegment::Key).collect(),
+            })),
+            other => Err(self.error_at(
+                token.offset,
+                &format!("Expected an operand but found {}", other),
+            )),
+        }
+    }
+
+    /// Parses the `.key`, `[index]` and `["key"]` accessors following a field name.
+    fn parse_path(&mut self, name: String) -> Result<FieldPath, RuleEngineError> {
+        let mut segments = vec![PathSegment::Key(name)];
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Dot) => {
+                    self.pos += 1;
+                    let token = self.next().ok_or_else(|| {
+                        self.error_at(
+                            self.end_offset(),
+                            "Expected a field name after '.' but reached end of expression",
+                        )
+                    })?;
+                    segments.push(match token.kind {
+                        TokenKind::Ident(key) => PathSegment::Key(key),
+                        TokenKind::Number(n) if n.is_u64() => {
+                            PathSegment::Index(n.as_u64().unwrap() as usize)
+                        }
+                        other => {
+                            return Err(self.error_at(
+                                token.offset,
+                                &format!("Expected a field name after '.' but found {}", other),
+                            ));
+                        }
+                    });
+                }
+                Some(TokenKind::LBracket) => {
+                    self.pos += 1;
+                    let token = self.next().ok_or_else(|| {
+                        self.error_at(
+                            self.end_offset(),
+                            "Expected an index after '[' but reached end of expression",
+                        )
+                    })?;
+                    segments.push(match token.kind {
+                        TokenKind::Number(n) if n.is_u64() => {
+                            PathSegment::Index(n.as_u64().unwrap() as usize)
+                        }
+                        TokenKind::Str(key) => PathSegment::Key(key),
+                        other => {
+                            return Err(self.error_at(
+                                token.offset,
+                                &format!(
+                                    "Expected a non-negative index or quoted key but found {}",
+                                    other
+                                ),
+                            ));
+                        }
+                    });
+                    self.expect(TokenKind::RBracket)?;
+                }
+                _ => return Ok(FieldPath { segments }),
+            }
+        }
+    }
+
+    fn parse_list(&mut self, start: usize) -> Result<Expr, RuleEngineError> {
+        let mut items = Vec::new();
+        if matches!(self.peek(), Some(t) if t.kind == TokenKind::RBracket) {
+            self.pos += 1;
+            return Ok(Expr::List(items));
+        }
+        loop {
+            items.push(self.parse_comparison()?);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::RBracket,
+                    ..
+                }) => return Ok(Expr::List(items)),
+                Some(token) => {
+                    return Err(self.error_at(
+                        token.offset,
+                        &format!("Expected ',' or ']' but found {}", token.kind),
+                    ));
+                }
+                None => return Err(self.error_at(start, "Unclosed list, expected ']'")),
+            }
+        }
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, RuleEngineError> {
+        let mut args = Vec::new();
+        if matches!(self.peek(), Some(t) if t.kind == TokenKind::RParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_comparison()?);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::RParen,
+                    ..
+                }) => return Ok(args),
+                Some(token) => {
+                    return Err(self.error_at(
+                        token.offset,
+                        &format!("Expected ',' or ')' but found {}", token.kind),
+                    ));
+                }
+                None => {
+                    return Err(
+                        self.error_at(self.end_offset(), "Unclosed argument list, expected ')'")
+                    );
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -20,8 +1407,8 @@ mod tests {
         let rules = parse_rules(json).unwrap();
         assert_eq!(rules.len(), 1);
         // assert_eq!(rules[0].condition, "age > 18");
-        assert_eq!(rules[0].outcome.key, "eligible");
-        assert_eq!(rules[0].outcome.value, serde_json::Value::Bool(true));
+        assert_eq!(rules[0].outcomes[0].key, "eligible");
+        assert_eq!(rules[0].outcomes[0].value, serde_json::Value::Bool(true));
     }
 
     #[test]
@@ -30,4 +1417,388 @@ mod tests {
         let result = parse_rules(json);
         assert!(matches!(result, Err(RuleEngineError::ParseError(_))));
     }
+
+    #[test]
+    fn test_parse_expression_without_spaces() {
+        let expr = parse_expression("a>5").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Compare {
+                op: CompareOp::Gt,
+                left: Box::new(Expr::Field(FieldPath::key("a"))),
+                right: Box::new(Expr::Literal(serde_json::json!(5))),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_quoted_literal() {
+        let expr = parse_expression(r#"name = "John \"JJ\" Smith""#).unwrap();
+        assert_eq!(
+            expr,
+            Expr::Compare {
+                op: CompareOp::Eq,
+                left: Box::new(Expr::Field(FieldPath::key("name"))),
+                right: Box::new(Expr::Literal(serde_json::json!("John \"JJ\" Smith"))),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_literals_and_parentheses() {
+        let expr = parse_expression("(active = true)").unwrap();
+        assert!(matches!(
+            expr,
+            Expr::Compare { op: CompareOp::Eq, right, .. }
+                if *right == Expr::Literal(serde_json::json!(true))
+        ));
+        assert_eq!(
+            parse_expression("-2.5e1").unwrap(),
+            Expr::Literal(serde_json::json!(-25.0))
+        );
+        assert_eq!(
+            parse_expression("null").unwrap(),
+            Expr::Literal(serde_json::Value::Null)
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_paths() {
+        let expected = FieldPath {
+            segments: vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(0),
+                PathSegment::Key("sku".to_string()),
+            ],
+        };
+        assert_eq!(
+            parse_expression("items[0].sku").unwrap(),
+            Expr::Field(expected.clone())
+        );
+        assert_eq!(
+            parse_expression("items.0.sku").unwrap(),
+            Expr::Field(expected)
+        );
+        assert_eq!(
+            parse_expression(r#"user["first name"]"#).unwrap(),
+            Expr::Field(FieldPath {
+                segments: vec![
+                    PathSegment::Key("user".to_string()),
+                    PathSegment::Key("first name".to_string()),
+                ],
+            })
+        );
+        assert_eq!(
+            parse_expression("/a~1b/c~0d").unwrap(),
+            Expr::Field(FieldPath {
+                segments: vec![
+                    PathSegment::Key("a/b".to_string()),
+                    PathSegment::Key("c~d".to_string()),
+                ],
+            })
+        );
+        assert!(parse_expression("items[-1]").is_err());
+        assert!(parse_expression("user.").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_keyword_operators() {
+        let expr = parse_expression(r#"country not in ["US", "CA"]"#).unwrap();
+        assert_eq!(
+            expr,
+            Expr::Compare {
+                op: CompareOp::NotIn,
+                left: Box::new(Expr::Field(FieldPath::key("country"))),
+                right: Box::new(Expr::List(vec![
+                    Expr::Literal(serde_json::json!("US")),
+                    Expr::Literal(serde_json::json!("CA")),
+                ])),
+            }
+        );
+        assert!(matches!(
+            parse_expression("a != 1").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Ne,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_expression(r#"name starts_with "Dr.""#).unwrap(),
+            Expr::Compare {
+                op: CompareOp::StartsWith,
+                ..
+            }
+        ));
+        assert_eq!(
+            parse_expression("balance >= minimum_balance").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Ge,
+                left: Box::new(Expr::Field(FieldPath::key("balance"))),
+                right: Box::new(Expr::Bareword("minimum_balance".to_string())),
+            }
+        );
+        assert!(parse_expression("a not b").is_err());
+        assert!(parse_expression("a in [1, 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_arithmetic_precedence() {
+        let field = |name: &str| Box::new(Expr::Field(FieldPath::key(name)));
+        let expr = parse_expression("(score + bonus) / 2 >= 70").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Compare {
+                op: CompareOp::Ge,
+                left: Box::new(Expr::Arithmetic {
+                    op: ArithOp::Div,
+                    left: Box::new(Expr::Arithmetic {
+                        op: ArithOp::Add,
+                        left: field("score"),
+                        right: field("bonus"),
+                    }),
+                    right: Box::new(Expr::Literal(serde_json::json!(2))),
+                }),
+                right: Box::new(Expr::Literal(serde_json::json!(70))),
+            }
+        );
+        assert_eq!(
+            parse_expression("a - b * -c").unwrap(),
+            Expr::Arithmetic {
+                op: ArithOp::Sub,
+                left: field("a"),
+                right: Box::new(Expr::Arithmetic {
+                    op: ArithOp::Mul,
+                    left: field("b"),
+                    right: Box::new(Expr::Negate(field("c"))),
+                }),
+            }
+        );
+        // A `/` after an operand divides; elsewhere it starts a JSON pointer.
+        assert_eq!(
+            parse_expression("/a/b / 2").unwrap(),
+            Expr::Arithmetic {
+                op: ArithOp::Div,
+                left: Box::new(Expr::Field(FieldPath {
+                    segments: vec![
+                        PathSegment::Key("a".to_string()),
+                        PathSegment::Key("b".to_string()),
+                    ],
+                })),
+                right: Box::new(Expr::Literal(serde_json::json!(2))),
+            }
+        );
+        assert!(parse_expression("a * ").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_coalesce_and_checks() {
+        assert_eq!(
+            parse_expression("nickname ?? name = Haile").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Eq,
+                left: Box::new(Expr::Coalesce {
+                    left: Box::new(Expr::Field(FieldPath::key("nickname"))),
+                    right: Box::new(Expr::Field(FieldPath::key("name"))),
+                }),
+                right: Box::new(Expr::Bareword("Haile".to_string())),
+            }
+        );
+        assert_eq!(
+            parse_expression("user.email exists").unwrap(),
+            Expr::Check {
+                op: CheckOp::Exists,
+                operand: Box::new(Expr::Field(FieldPath {
+                    segments: vec![
+                        PathSegment::Key("user".to_string()),
+                        PathSegment::Key("email".to_string()),
+                    ],
+                })),
+            }
+        );
+        assert!(matches!(
+            parse_expression("discount ?? 0 + 5 is_empty").unwrap(),
+            Expr::Check {
+                op: CheckOp::IsEmpty,
+                ..
+            }
+        ));
+        assert!(parse_expression("a ? b").is_err());
+        assert!(parse_expression("tags is_null = true").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_between() {
+        let between =
+            |value: Expr, low: i64, high: i64, low_inclusive, high_inclusive| Expr::Between {
+                value: Box::new(value),
+                low: Box::new(Expr::Literal(Value::from(low))),
+                high: Box::new(Expr::Literal(Value::from(high))),
+                low_inclusive,
+                high_inclusive,
+            };
+        let age = || Expr::Field(FieldPath::key("age"));
+        assert_eq!(
+            parse_expression("age between 18 and 65").unwrap(),
+            between(age(), 18, 65, true, true)
+        );
+        assert_eq!(
+            parse_expression("age between [18, 65)").unwrap(),
+            between(age(), 18, 65, true, false)
+        );
+        assert_eq!(
+            parse_expression("age between (18,65]").unwrap(),
+            between(age(), 18, 65, false, true)
+        );
+        assert!(matches!(
+            parse_expression("age between minimum and maximum").unwrap(),
+            Expr::Between { low, .. } if *low == Expr::Bareword("minimum".to_string())
+        ));
+        assert!(parse_expression("age between 18 or 65").is_err());
+        assert!(parse_expression("age between [18, 65").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_matches() {
+        assert_eq!(
+            parse_expression(r#"email not matches "^[^@]+@corp\\.com$""#).unwrap(),
+            Expr::Compare {
+                op: CompareOp::NotMatches,
+                left: Box::new(Expr::Field(FieldPath::key("email"))),
+                right: Box::new(Expr::Literal(Value::from(r"^[^@]+@corp\.com$"))),
+            }
+        );
+        let err = parse_expression("email not like x").unwrap_err();
+        assert!(
+            matches!(err, RuleEngineError::ParseError(msg) if msg.starts_with("Expected 'in' or 'matches' after 'not' at position 6"))
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_version_literals() {
+        let version = |text: &str| Expr::Version(semver::Version::parse(text).unwrap());
+        assert_eq!(
+            parse_expression("app_version >= 2.10.0").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Ge,
+                left: Box::new(Expr::Field(FieldPath::key("app_version"))),
+                right: Box::new(version("2.10.0")),
+            }
+        );
+        assert_eq!(
+            parse_expression("1.0.0-rc.1+build.5").unwrap(),
+            version("1.0.0-rc.1+build.5")
+        );
+        assert_eq!(
+            parse_expression("2.3").unwrap(),
+            Expr::Literal(Value::from(2.3))
+        );
+        assert!(parse_expression("01.2.3").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_ip_literals() {
+        let ip = |text: &str| Expr::Ip(crate::network::parse_network(text).unwrap());
+        assert_eq!(
+            parse_expression("client_ip in 10.0.0.0/8").unwrap(),
+            Expr::Compare {
+                op: CompareOp::In,
+                left: Box::new(Expr::Field(FieldPath::key("client_ip"))),
+                right: Box::new(ip("10.0.0.0/8")),
+            }
+        );
+        assert_eq!(
+            parse_expression("2001:db8::/32").unwrap(),
+            ip("2001:db8::/32")
+        );
+        assert_eq!(parse_expression("fe80::1").unwrap(), ip("fe80::1"));
+        assert_eq!(
+            parse_expression("face").unwrap(),
+            Expr::Field(FieldPath::key("face"))
+        );
+        assert!(matches!(
+            parse_expression("client_ip in_cidr [10.0.0.0/8, 192.168.0.0/16]").unwrap(),
+            Expr::Compare {
+                op: CompareOp::InCidr,
+                ..
+            }
+        ));
+        let err = parse_expression("client_ip in 10.0.0.5/8").unwrap_err();
+        assert!(err.to_string().contains("host bits are set"));
+        assert!(parse_expression("client_ip = 300.1.1.1").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_constants() {
+        assert_eq!(
+            parse_expression("age >= $min_age").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Ge,
+                left: Box::new(Expr::Field(FieldPath::key("age"))),
+                right: Box::new(Expr::Constant("min_age".to_string())),
+            }
+        );
+        assert!(matches!(
+            parse_expression("$limit/2").unwrap(),
+            Expr::Arithmetic {
+                op: ArithOp::Div,
+                ..
+            }
+        ));
+        let err = parse_expression("age >= $").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Expected a constant name after '$' at position 7")
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_temporal_literals() {
+        let expr = parse_expression("now() - created_at > 30d").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Compare {
+                op: CompareOp::Gt,
+                left: Box::new(Expr::Arithmetic {
+                    op: ArithOp::Sub,
+                    left: Box::new(Expr::Call {
+                        name: "now".to_string(),
+                        args: vec![],
+                    }),
+                    right: Box::new(Expr::Field(FieldPath::key("created_at"))),
+                }),
+                right: Box::new(Expr::Duration(TimeDelta::days(30))),
+            }
+        );
+        assert_eq!(
+            parse_expression("2024-01-01T02:00:00+02:00").unwrap(),
+            Expr::DateTime(value::parse_datetime("2024-01-01").unwrap())
+        );
+        assert!(matches!(
+            parse_expression("last_login within 1h30m").unwrap(),
+            Expr::Compare {
+                op: CompareOp::Within,
+                ..
+            }
+        ));
+        assert!(parse_expression("signup_date > 2024-02-30").is_err());
+        assert!(parse_expression("age > 5min").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_errors_report_position() {
+        let err = parse_expression(r#"name = "unterminated"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Parse error: Unterminated string literal at position 7 in 'name = "unterminated'"#
+        );
+
+        let err = parse_expression("age > ").unwrap_err();
+        assert!(err.to_string().contains("reached end of expression"));
+
+        let err = parse_expression("age > 18 18").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unexpected number 18 at position 9")
+        );
+    }
 }
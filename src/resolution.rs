@@ -0,0 +1,167 @@
+use crate::error::RuleEngineError;
+use crate::number::Number;
+use crate::parser::ArithOp;
+
+/// How [`crate::RuleEngine::evaluate_resolved`] combines the values of outcomes
+/// that share a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResolutionStrategy {
+    /// The value from the rule with the highest priority; among rules of equal
+    /// priority, the one defined first.
+    #[default]
+    HighestPriority,
+    /// The value from the rule defined first, regardless of priority.
+    First,
+    /// The value from the rule defined last, regardless of priority.
+    Last,
+    /// All values as an array, in priority order.
+    Collect,
+    /// The sum of the values, which must be numbers.
+    Sum,
+    /// The largest of the values, which must be numbers.
+    Max,
+    /// The smallest of the values, which must be numbers.
+    Min,
+    /// The values, which must be objects, merged recursively. Where they disagree
+    /// on a key that is not an object in both, the higher-priority value wins.
+    DeepMerge,
+}
+
+impl ResolutionStrategy {
+    fn name(&self) -> &'static str {
+        match self {
+            ResolutionStrategy::HighestPriority => "HighestPriority",
+            ResolutionStrategy::First => "First",
+            ResolutionStrategy::Last => "Last",
+            ResolutionStrategy::Collect => "Collect",
+            ResolutionStrategy::Sum => "Sum",
+            ResolutionStrategy::Max => "Max",
+            ResolutionStrategy::Min => "Min",
+            ResolutionStrategy::DeepMerge => "DeepMerge",
+        }
+    }
+}
+
+/// Combines the values emitted for `key`, given in priority order together with
+/// the index of the rule that emitted them. `values` is never empty.
+pub(crate) fn resolve(
+    strategy: ResolutionStrategy,
+    key: &str,
+    values: Vec<(usize, serde_json::Value)>,
+    decimal: bool,
+) -> Result<serde_json::Value, RuleEngineError> {
+    let error = |message: String| {
+        RuleEngineError::EvaluationError(format!(
+            "Cannot resolve outcome '{}' with {}: {}",
+            key,
+            strategy.name(),
+            message
+        ))
+    };
+    match strategy {
+        ResolutionStrategy::HighestPriority => Ok(values.into_iter().next().unwrap().1),
+        // Outcomes of the same rule keep their order: the first one wins for
+        // `First` and the last one for `Last`.
+        ResolutionStrategy::First => Ok(values
+            .into_iter()
+            .min_by_key(|(index, _)| *index)
+            .unwrap()
+            .1),
+        ResolutionStrategy::Last => Ok(values
+            .into_iter()
+            .max_by_key(|(index, _)| *index)
+            .unwrap()
+            .1),
+        ResolutionStrategy::Collect => Ok(serde_json::Value::Array(
+            values.into_iter().map(|(_, value)| value).collect(),
+        )),
+        ResolutionStrategy::Sum | ResolutionStrategy::Max | ResolutionStrategy::Min => {
+            let mut result: Option<Number> = None;
+            for (_, value) in &values {
+                let serde_json::Value::Number(number) = value else {
+                    return Err(error(format!("{} is not a number", value)));
+                };
+                let number = Number::from_json(number, decimal);
+                result = Some(match result {
+                    None => number,
+                    Some(current) => match strategy {
+                        ResolutionStrategy::Sum => {
+                            Number::apply(ArithOp::Add, &current, &number, decimal)
+                        }
+                        ResolutionStrategy::Max if number > current => number,
+                        ResolutionStrategy::Min if number < current => number,
+                        _ => current,
+                    },
+                });
+            }
+            result
+                .and_then(|number| number.to_json())
+                .map(serde_json::Value::Number)
+                .ok_or_else(|| error("the result is not a finite number".to_string()))
+        }
+        ResolutionStrategy::DeepMerge => {
+            let mut merged = serde_json::Value::Object(serde_json::Map::new());
+            // Lowest priority first, so that higher-priority values overwrite.
+            for (_, value) in values.into_iter().rev() {
+                if !value.is_object() {
+                    return Err(error(format!("{} is not an object", value)));
+                }
+                deep_merge(&mut merged, value);
+            }
+            Ok(merged)
+        }
+    }
+}
+
+fn deep_merge(target: &mut serde_json::Value, value: serde_json::Value) {
+    match (target, value) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(map)) => {
+            for (key, value) in map {
+                match target.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deep_merge_prefers_higher_priority() {
+        let values = vec![
+            (1, json!({"limits": {"daily": 500}, "tags": ["vip"]})),
+            (
+                0,
+                json!({"limits": {"daily": 100, "monthly": 2000}, "tags": ["new"]}),
+            ),
+        ];
+        let merged = resolve(ResolutionStrategy::DeepMerge, "account", values, false).unwrap();
+        assert_eq!(
+            merged,
+            json!({"limits": {"daily": 500, "monthly": 2000}, "tags": ["vip"]})
+        );
+    }
+
+    #[test]
+    fn test_numeric_strategies_are_exact() {
+        let values = || {
+            vec![
+                (0, json!(9007199254740993u64)),
+                (1, json!(1)),
+                (2, json!(-4)),
+            ]
+        };
+        let resolve = |strategy| resolve(strategy, "total", values(), false).unwrap();
+        assert_eq!(resolve(ResolutionStrategy::Sum), json!(9007199254740990u64));
+        assert_eq!(resolve(ResolutionStrategy::Max), json!(9007199254740993u64));
+        assert_eq!(resolve(ResolutionStrategy::Min), json!(-4));
+    }
+}
@@ -1,10 +1,25 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-/// A single rule with a condition, outcome, and priority.
+/// Rules together with the constants their conditions and templates refer to,
+/// as declared by a rules document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+    /// Values referred to as `$name` in conditions and as `constants.name` in
+    /// outcome templates.
+    #[serde(default)]
+    pub constants: HashMap<String, serde_json::Value>,
+}
+
+/// A single rule with a condition, outcomes, and priority.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
     pub condition: Condition,
-    pub outcome: Outcome,
+    /// The outcomes emitted, in order, when the condition holds. In JSON either
+    /// `"outcomes": [...]` or a single `"outcome": {...}`.
+    #[serde(alias = "outcome", deserialize_with = "one_or_many")]
+    pub outcomes: Vec<Outcome>,
     #[serde(default = "default_priority")]
     pub priority: i32,
 }
@@ -13,10 +28,7 @@ impl Default for Rule {
     fn default() -> Self {
         Rule {
             condition: Condition::Simple(String::new()),
-            outcome: Outcome {
-                key: String::new(),
-                value: serde_json::Value::Null,
-            },
+            outcomes: vec![Outcome::default()],
             priority: default_priority(),
         }
     }
@@ -26,6 +38,24 @@ fn default_priority() -> i32 {
     0
 }
 
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<Outcome>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    // Decided by the shape of the value rather than an untagged enum, so that an
+    // invalid outcome reports what is wrong with it.
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Array(outcomes) => {
+            Vec::<Outcome>::deserialize(serde_json::Value::Array(outcomes))
+                .map_err(D::Error::custom)
+        }
+        outcome => Outcome::deserialize(outcome)
+            .map(|outcome| vec![outcome])
+            .map_err(D::Error::custom),
+    }
+}
+
 /// A condition to evaluate, either a simple expression or a nested combination.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value")]
@@ -33,11 +63,89 @@ pub enum Condition {
     Simple(String),
     And(Vec<Condition>),
     Or(Vec<Condition>),
+    Not(Box<Condition>),
+    /// True when exactly one of the conditions holds.
+    Xor(Vec<Condition>),
+    /// True when at least `n` of the conditions hold.
+    AtLeast {
+        n: usize,
+        conditions: Vec<Condition>,
+    },
+    /// True when at most `n` of the conditions hold.
+    AtMost {
+        n: usize,
+        conditions: Vec<Condition>,
+    },
+    /// True when `condition` holds for at least one element of the array `field`.
+    /// The element is bound to the name given by `as` (default `item`), both in
+    /// `condition` and in the outcome template.
+    Any {
+        field: String,
+        #[serde(rename = "as", default = "default_binding")]
+        binding: String,
+        condition: Box<Condition>,
+    },
+    /// True when `condition` holds for every element of the array `field`.
+    All {
+        field: String,
+        #[serde(rename = "as", default = "default_binding")]
+        binding: String,
+        condition: Box<Condition>,
+    },
+    /// True when `condition` holds for no element of the array `field`.
+    None {
+        field: String,
+        #[serde(rename = "as", default = "default_binding")]
+        binding: String,
+        condition: Box<Condition>,
+    },
+    /// A reference to a condition declared in the `conditions` section of a rules
+    /// document, replaced by that condition in [`crate::parser::parse_rules`].
+    Ref(String),
+}
+
+fn default_binding() -> String {
+    "item".to_string()
 }
 
 /// The result of a rule evaluation, supporting templated values.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Outcome {
     pub key: String,
+    #[serde(default)]
     pub value: serde_json::Value, // Can be a template like "Hello, {{name}}!"
+    /// An expression computing the value instead, e.g. `price * quantity`, in the
+    /// language of conditions. An outcome has either a `value` or an `expr`, and
+    /// an `expr` takes no `type`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
+    /// What the templates in `value` render to. Templates render to strings unless
+    /// a type is given, in which case the rendered text is converted to it. A type
+    /// on a value without templates is rejected when the rules are compiled.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<OutcomeType>,
+}
+
+/// The type a templated outcome value is converted to, e.g. `"type": "number"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutcomeType {
+    String,
+    /// An integer or decimal number, e.g. `"90"` or `"12.5"`.
+    Number,
+    /// `true` or `false`.
+    Bool,
+    /// Any JSON text, e.g. `{"tier": "gold"}` or `[1, 2]`.
+    Json,
+}
+
+impl OutcomeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutcomeType::String => "string",
+            OutcomeType::Number => "number",
+            OutcomeType::Bool => "bool",
+            OutcomeType::Json => "json",
+        }
+    }
 }
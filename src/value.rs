@@ -0,0 +1,295 @@
+use crate::network;
+use crate::number::Number;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, Utc};
+use ipnet::IpNet;
+use std::borrow::Cow;
+
+/// A value produced while evaluating an expression: JSON from the input, context
+/// or a literal, or one of the types JSON has no representation for.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value<'a> {
+    Json(Cow<'a, serde_json::Value>),
+    DateTime(DateTime<Utc>),
+    Duration(TimeDelta),
+    Version(semver::Version),
+    /// An IP address (a single-host network) or a CIDR block.
+    Ip(IpNet),
+    /// A decimal computed by arithmetic, kept as is because it may have more
+    /// digits than a JSON number holds.
+    Number(Number),
+    /// A reference to a field that does not exist. Operators propagate it, and a
+    /// condition that evaluates to it is false.
+    Missing,
+}
+
+impl<'a> Value<'a> {
+    pub(crate) fn borrowed(value: &'a serde_json::Value) -> Self {
+        Value::Json(Cow::Borrowed(value))
+    }
+
+    pub(crate) fn owned(value: serde_json::Value) -> Self {
+        Value::Json(Cow::Owned(value))
+    }
+
+    /// The value as JSON. Date-times become RFC 3339 strings and durations use the
+    /// literal syntax of the expression language, e.g. `"1h30m"`.
+    pub(crate) fn to_json(&self) -> Cow<'_, serde_json::Value> {
+        match self {
+            Value::Json(value) => Cow::Borrowed(value.as_ref()),
+            other => Cow::Owned(other.clone().into_json()),
+        }
+    }
+
+    pub(crate) fn into_json(self) -> serde_json::Value {
+        match self {
+            Value::Json(value) => value.into_owned(),
+            Value::DateTime(datetime) => {
+                serde_json::Value::String(datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            Value::Duration(duration) => serde_json::Value::String(format_duration(duration)),
+            Value::Version(version) => serde_json::Value::String(version.to_string()),
+            Value::Ip(network) => serde_json::Value::String(network::format_network(&network)),
+            Value::Number(number) => number
+                .to_json()
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            Value::Missing => serde_json::Value::Null,
+        }
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Json(value) => match value.as_ref() {
+                serde_json::Value::Null => "null",
+                serde_json::Value::Bool(_) => "boolean",
+                serde_json::Value::Number(_) => "number",
+                serde_json::Value::String(_) => "string",
+                serde_json::Value::Array(_) => "array",
+                serde_json::Value::Object(_) => "object",
+            },
+            Value::DateTime(_) => "datetime",
+            Value::Duration(_) => "duration",
+            Value::Version(_) => "version",
+            Value::Ip(_) => "ip",
+            Value::Number(_) => "number",
+            Value::Missing => "missing",
+        }
+    }
+
+    pub(crate) fn is_missing(&self) -> bool {
+        matches!(self, Value::Missing)
+    }
+
+    /// Missing values and JSON `null`.
+    pub(crate) fn is_null(&self) -> bool {
+        match self {
+            Value::Json(value) => value.is_null(),
+            Value::Missing => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_temporal(&self) -> bool {
+        matches!(self, Value::DateTime(_) | Value::Duration(_))
+    }
+
+    /// Values of a type JSON has no representation for.
+    pub(crate) fn is_typed(&self) -> bool {
+        matches!(
+            self,
+            Value::DateTime(_) | Value::Duration(_) | Value::Version(_) | Value::Ip(_)
+        )
+    }
+
+    /// Interprets the value as a date-time: RFC 3339 strings, plain dates
+    /// (midnight UTC) and numbers as Unix timestamps in seconds.
+    pub(crate) fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Value::DateTime(datetime) => Some(*datetime),
+            Value::Json(value) => match value.as_ref() {
+                serde_json::Value::String(s) => parse_datetime(s),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(seconds) => DateTime::from_timestamp(seconds, 0),
+                    None => {
+                        let seconds = n.as_f64()?;
+                        DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+                    }
+                },
+                _ => None,
+            },
+            Value::Number(_) => Value::owned(self.clone().into_json()).as_datetime(),
+            Value::Duration(_) | Value::Version(_) | Value::Ip(_) | Value::Missing => None,
+        }
+    }
+
+    /// Interprets the value as a duration: duration strings such as `"7d"` and
+    /// numbers as seconds.
+    pub(crate) fn as_duration(&self) -> Option<TimeDelta> {
+        match self {
+            Value::Duration(duration) => Some(*duration),
+            Value::Json(value) => match value.as_ref() {
+                serde_json::Value::String(s) => parse_duration(s),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(seconds) => TimeDelta::try_seconds(seconds),
+                    None => TimeDelta::try_milliseconds((n.as_f64()? * 1000.0) as i64),
+                },
+                _ => None,
+            },
+            Value::Number(_) => Value::owned(self.clone().into_json()).as_duration(),
+            Value::DateTime(_) | Value::Version(_) | Value::Ip(_) | Value::Missing => None,
+        }
+    }
+
+    /// Interprets the value as an IP address or CIDR block.
+    pub(crate) fn as_network(&self) -> Option<IpNet> {
+        match self {
+            Value::Ip(network) => Some(*network),
+            Value::Json(value) => network::parse_network(value.as_str()?),
+            _ => None,
+        }
+    }
+
+    /// Interprets the value as a semantic version, see [`parse_version`].
+    pub(crate) fn as_version(&self) -> Option<semver::Version> {
+        match self {
+            Value::Version(version) => Some(version.clone()),
+            Value::Json(value) => parse_version(value.as_str()?),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a semantic version, accepting a leading `v` and missing minor or patch
+/// components (`v2.3` is `2.3.0`).
+pub(crate) fn parse_version(text: &str) -> Option<semver::Version> {
+    let text = text.trim();
+    let text = text.strip_prefix('v').unwrap_or(text);
+    if let Ok(version) = semver::Version::parse(text) {
+        return Some(version);
+    }
+    let core_end = text.find(['-', '+']).unwrap_or(text.len());
+    let padding = match text[..core_end].matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => return None,
+    };
+    let padded = format!("{}{}{}", &text[..core_end], padding, &text[core_end..]);
+    semver::Version::parse(&padded).ok()
+}
+
+/// Parses an RFC 3339 date-time, a date-time without offset (taken as UTC) or a
+/// plain `YYYY-MM-DD` date (midnight UTC).
+pub(crate) fn parse_datetime(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(naive.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|naive| naive.and_utc())
+}
+
+const DURATION_UNITS: [(&str, i64); 6] = [
+    ("w", 7 * 24 * 60 * 60 * 1000),
+    ("d", 24 * 60 * 60 * 1000),
+    ("h", 60 * 60 * 1000),
+    ("m", 60 * 1000),
+    ("s", 1000),
+    ("ms", 1),
+];
+
+/// Parses a duration such as `30m`, `7d` or `1h30m`. Units are `w`, `d`, `h`, `m`,
+/// `s` and `ms`.
+pub(crate) fn parse_duration(text: &str) -> Option<TimeDelta> {
+    let (negative, mut rest) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let mut millis: i64 = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (_, scale) = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == &rest[..unit])?;
+        millis = millis.checked_add(amount.checked_mul(*scale)?)?;
+        rest = &rest[unit..];
+    }
+    TimeDelta::try_milliseconds(if negative { -millis } else { millis })
+}
+
+/// Formats a duration using the largest units first, e.g. `1h30m`.
+pub(crate) fn format_duration(duration: TimeDelta) -> String {
+    let mut millis = duration.num_milliseconds();
+    if millis == 0 {
+        return "0s".to_string();
+    }
+    let mut text = String::new();
+    if millis < 0 {
+        text.push('-');
+        millis = -millis;
+    }
+    for (name, scale) in DURATION_UNITS {
+        if millis >= scale {
+            text.push_str(&format!("{}{}", millis / scale, name));
+            millis %= scale;
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_duration() {
+        assert_eq!(parse_duration("7d"), Some(TimeDelta::days(7)));
+        assert_eq!(parse_duration("1h30m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("250ms"), Some(TimeDelta::milliseconds(250)));
+        assert_eq!(parse_duration("5min"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(format_duration(TimeDelta::minutes(90)), "1h30m");
+        assert_eq!(format_duration(TimeDelta::days(-14)), "-2w");
+    }
+
+    #[test]
+    fn test_parse_version_is_lenient() {
+        assert_eq!(parse_version("v2.3"), semver::Version::parse("2.3.0").ok());
+        assert_eq!(
+            parse_version("2-beta.1"),
+            semver::Version::parse("2.0.0-beta.1").ok()
+        );
+        assert_eq!(
+            parse_version("2.10.1"),
+            semver::Version::parse("2.10.1").ok()
+        );
+        assert_eq!(parse_version("2.x"), None);
+    }
+
+    #[test]
+    fn test_parse_datetime_formats() {
+        let midnight = parse_datetime("2024-01-01").unwrap();
+        assert_eq!(midnight.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        let offset = parse_datetime("2024-01-01T02:00:00+02:00").unwrap();
+        assert_eq!(offset, midnight);
+        assert!(parse_datetime("2024-13-01").is_none());
+    }
+}
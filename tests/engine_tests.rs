@@ -1,14 +1,18 @@
-use dynarule::{Condition, Outcome, Rule, RuleEngine, RuleEngineError};
+use dynarule::{
+    Condition, MissingKeyPolicy, Outcome, OutcomeType, ResolutionStrategy, Rule, RuleEngine,
+    RuleEngineError,
+};
 use std::collections::HashMap;
 
 #[test]
 fn test_basic_evaluation_greater_than() {
     let rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "eligible".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -32,10 +36,11 @@ fn test_basic_evaluation_greater_than() {
 fn test_basic_evaluation_equals() {
     let rule = Rule {
         condition: Condition::Simple("status = active".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("granted"),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -59,10 +64,11 @@ fn test_basic_evaluation_equals() {
 fn test_missing_input_key() {
     let rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "eligible".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -81,10 +87,11 @@ fn test_missing_input_key() {
 fn test_basic_evaluation_less_than() {
     let rule = Rule {
         condition: Condition::Simple("age < 20".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "youth".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -106,10 +113,11 @@ fn test_nested_and_condition() {
             Condition::Simple("age > 18".to_string()),
             Condition::Simple("status = active".to_string()),
         ]),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("granted"),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -138,10 +146,11 @@ fn test_nested_or_condition() {
             Condition::Simple("age > 18".to_string()),
             Condition::Simple("status = active".to_string()),
         ]),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "eligible".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -161,10 +170,11 @@ fn test_nested_or_condition() {
 fn test_custom_function() {
     let rule = Rule {
         condition: Condition::Simple("length(name) > 2".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "valid".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]).with_function("length", |value| {
@@ -194,10 +204,11 @@ fn test_custom_function() {
 fn test_unknown_function() {
     let rule = Rule {
         condition: Condition::Simple("unknown(name) > 5".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "valid".to_string(),
             value: serde_json::json!(true),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -214,18 +225,20 @@ fn test_unknown_function() {
 fn test_rule_priority() {
     let low_priority_rule = Rule {
         condition: Condition::Simple("age > 10".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("low"),
-        },
+            ..Default::default()
+        }],
         priority: 1,
     };
     let high_priority_rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("high"),
-        },
+            ..Default::default()
+        }],
         priority: 10,
     };
     let engine =
@@ -244,18 +257,20 @@ fn test_rule_priority() {
 fn test_no_stop_on_first_match() {
     let low_priority_rule = Rule {
         condition: Condition::Simple("age > 10".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("low"),
-        },
+            ..Default::default()
+        }],
         priority: 1,
     };
     let high_priority_rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "access".to_string(),
             value: serde_json::json!("high"),
-        },
+            ..Default::default()
+        }],
         priority: 10,
     };
     let engine = RuleEngine::new(vec![high_priority_rule, low_priority_rule]); // Default: no stop
@@ -274,10 +289,11 @@ fn test_no_stop_on_first_match() {
 fn test_templated_outcome() {
     let rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "message".to_string(),
             value: serde_json::json!("Hello, {{name}}!"),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -296,10 +312,11 @@ fn test_templated_outcome() {
 fn test_missing_template_key() {
     let rule = Rule {
         condition: Condition::Simple("age > 18".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "message".to_string(),
             value: serde_json::json!("Hello, {{unknown}}!"),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -315,10 +332,11 @@ fn test_missing_template_key() {
 fn test_contextual_data() {
     let rule = Rule {
         condition: Condition::Simple("time > 12".to_string()),
-        outcome: Outcome {
+        outcomes: vec![Outcome {
             key: "message".to_string(),
             value: serde_json::json!("Afternoon, {{name}}!"),
-        },
+            ..Default::default()
+        }],
         ..Default::default()
     };
     let engine = RuleEngine::new(vec![rule]);
@@ -332,3 +350,1456 @@ fn test_contextual_data() {
     assert_eq!(outcomes[0].key, "message");
     assert_eq!(outcomes[0].value, serde_json::json!("Afternoon, Haile!"));
 }
+
+#[test]
+fn test_quoted_literal_with_spaces() {
+    let rule = Rule {
+        condition: Condition::Simple(r#"name = "John Smith""#.to_string()),
+        outcomes: vec![Outcome {
+            key: "greeting".to_string(),
+            value: serde_json::json!("Hello, {{name}}!"),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert("name".to_string(), serde_json::json!("John Smith"));
+    let context = HashMap::new();
+    let outcomes = engine.evaluate(&input, &context).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].value, serde_json::json!("Hello, John Smith!"));
+}
+
+#[test]
+fn test_expression_without_whitespace() {
+    let rule = Rule {
+        condition: Condition::Simple("a>5".to_string()),
+        outcomes: vec![Outcome {
+            key: "big".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert("a".to_string(), serde_json::json!(6));
+    let context = HashMap::new();
+    let outcomes = engine.evaluate(&input, &context).unwrap();
+    assert_eq!(outcomes.len(), 1);
+}
+
+#[test]
+fn test_malformed_expression() {
+    let rule = Rule {
+        condition: Condition::Simple("age > ".to_string()),
+        outcomes: vec![Outcome {
+            key: "eligible".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(25));
+    let context = HashMap::new();
+    let result = engine.evaluate(&input, &context);
+    assert!(matches!(result, Err(RuleEngineError::ParseError(_))));
+}
+
+#[test]
+fn test_nested_field_paths() {
+    let rule = Rule {
+        condition: Condition::And(vec![
+            Condition::Simple("user.address.country = ET".to_string()),
+            Condition::Simple("items[1].price > 100".to_string()),
+            Condition::Simple("/items/0/sku = \"A-1\"".to_string()),
+        ]),
+        outcomes: vec![Outcome {
+            key: "message".to_string(),
+            value: serde_json::json!(
+                "{{ items[1].sku }} to {{ user | pointer(path=\"/address/country\") }}"
+            ),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert(
+        "user".to_string(),
+        serde_json::json!({"address": {"country": "ET"}}),
+    );
+    input.insert(
+        "items".to_string(),
+        serde_json::json!([{"sku": "A-1", "price": 10}, {"sku": "B-2", "price": 250}]),
+    );
+    let context = HashMap::new();
+    let outcomes = engine.evaluate(&input, &context).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].value, serde_json::json!("B-2 to ET"));
+
+    input.insert("items".to_string(), serde_json::json!([{"sku": "A-1"}]));
+    let result = engine.evaluate(&input, &context);
+    assert!(
+        matches!(result, Err(RuleEngineError::EvaluationError(msg)) if msg == "Key 'items[1].price' not found")
+    );
+}
+
+#[test]
+fn test_custom_function_with_nested_argument() {
+    let rule = Rule {
+        condition: Condition::Simple("length(user.name) > 3".to_string()),
+        outcomes: vec![Outcome {
+            key: "valid".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]).with_function("length", |value| {
+        let len = value
+            .as_str()
+            .ok_or_else(|| RuleEngineError::EvaluationError("Expected string".to_string()))?
+            .len();
+        Ok(serde_json::json!(len))
+    });
+
+    let mut input = HashMap::new();
+    input.insert("user".to_string(), serde_json::json!({"name": "Haile"}));
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 1);
+}
+
+#[test]
+fn test_not_condition() {
+    let rule = Rule {
+        condition: Condition::Not(Box::new(Condition::Simple("status = banned".to_string()))),
+        outcomes: vec![Outcome {
+            key: "access".to_string(),
+            value: serde_json::json!("granted"),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert("status".to_string(), serde_json::json!("active"));
+    let context = HashMap::new();
+    assert_eq!(engine.evaluate(&input, &context).unwrap().len(), 1);
+
+    input.insert("status".to_string(), serde_json::json!("banned"));
+    assert_eq!(engine.evaluate(&input, &context).unwrap().len(), 0);
+}
+
+#[test]
+fn test_xor_condition() {
+    let rule = Rule {
+        condition: Condition::Xor(vec![
+            Condition::Simple("age > 18".to_string()),
+            Condition::Simple("status = active".to_string()),
+        ]),
+        outcomes: vec![Outcome {
+            key: "exclusive".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]);
+
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(25));
+    input.insert("status".to_string(), serde_json::json!("inactive"));
+    let context = HashMap::new();
+    assert_eq!(engine.evaluate(&input, &context).unwrap().len(), 1);
+
+    input.insert("status".to_string(), serde_json::json!("active"));
+    assert_eq!(engine.evaluate(&input, &context).unwrap().len(), 0);
+}
+
+#[test]
+fn test_threshold_conditions_short_circuit() {
+    // The third condition references a missing key and would fail if evaluated.
+    let conditions = vec![
+        Condition::Simple("age > 18".to_string()),
+        Condition::Simple("score > 50".to_string()),
+        Condition::Simple("missing > 0".to_string()),
+    ];
+    let at_least = Rule {
+        condition: Condition::AtLeast {
+            n: 2,
+            conditions: conditions.clone(),
+        },
+        outcomes: vec![Outcome {
+            key: "at_least".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let at_most = Rule {
+        condition: Condition::AtMost { n: 1, conditions },
+        outcomes: vec![Outcome {
+            key: "at_most".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![at_least, at_most]);
+
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(25));
+    input.insert("score".to_string(), serde_json::json!(80));
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].key, "at_least");
+}
+
+/// Whether a single rule with `condition` matches `input`, on an engine set up by
+/// `configure`.
+fn matches_condition(
+    condition: Condition,
+    input: serde_json::Value,
+    configure: impl FnOnce(RuleEngine) -> RuleEngine,
+) -> Result<bool, RuleEngineError> {
+    let rule = Rule {
+        condition,
+        outcomes: vec![Outcome {
+            key: "matched".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = configure(RuleEngine::new(vec![rule]));
+    let input: HashMap<String, serde_json::Value> = serde_json::from_value(input).unwrap();
+    Ok(!engine.evaluate(&input, &HashMap::new())?.is_empty())
+}
+
+fn matches_with(
+    condition: &str,
+    input: serde_json::Value,
+    configure: impl FnOnce(RuleEngine) -> RuleEngine,
+) -> Result<bool, RuleEngineError> {
+    matches_condition(Condition::Simple(condition.to_string()), input, configure)
+}
+
+fn matches_rule(condition: &str, input: serde_json::Value) -> Result<bool, RuleEngineError> {
+    matches_with(condition, input, |engine| engine)
+}
+
+#[test]
+fn test_membership_operators() {
+    let input = serde_json::json!({"country": "US", "code": 3, "tags": ["vip", "beta"]});
+    assert!(matches_rule(r#"country in ["US", "CA"]"#, input.clone()).unwrap());
+    assert!(matches_rule("country in [US, CA]", input.clone()).unwrap());
+    assert!(!matches_rule(r#"country not in ["US", "CA"]"#, input.clone()).unwrap());
+    assert!(matches_rule("code in [1, 2, 3]", input.clone()).unwrap());
+    assert!(matches_rule("country != CA", input.clone()).unwrap());
+    assert!(matches_rule(r#"tags contains "vip""#, input.clone()).unwrap());
+    assert!(!matches_rule(r#"tags contains "alpha""#, input.clone()).unwrap());
+}
+
+#[test]
+fn test_string_operators() {
+    let input = serde_json::json!({"email": "haile@corp.com", "zip": 94105, "steps": [1, 2, 3]});
+    assert!(matches_rule(r#"email contains "@corp""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"email starts_with "haile""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"email ends_with ".com""#, input.clone()).unwrap());
+    // Numbers are matched by their decimal text.
+    assert!(matches_rule("zip starts_with 94", input.clone()).unwrap());
+    assert!(matches_rule("zip ends_with 105", input.clone()).unwrap());
+    assert!(matches_rule("zip contains 41", input.clone()).unwrap());
+    assert!(matches_rule(r#"zip contains "41""#, input.clone()).unwrap());
+    assert!(!matches_rule("zip starts_with 41", input.clone()).unwrap());
+    assert!(!matches_rule("zip ends_with 41", input.clone()).unwrap());
+    assert!(!matches_rule("zip contains 99", input.clone()).unwrap());
+    assert!(matches_rule("steps ends_with 3", input.clone()).unwrap());
+}
+
+#[test]
+fn test_operator_type_mismatch() {
+    let input = serde_json::json!({"active": true});
+    let result = matches_rule(r#"active contains "t""#, input);
+    assert!(matches!(
+        result,
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator 'contains' cannot be applied to boolean and string"
+    ));
+    let input = serde_json::json!({"country": "5"});
+    assert!(matches!(
+        matches_rule("country in 5", input),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator 'in' cannot be applied to string and number"
+    ));
+}
+
+#[test]
+fn test_type_aware_equality() {
+    let input =
+        serde_json::json!({"active": true, "count": 3, "ratio": 0.5, "code": "42", "note": null});
+    assert!(matches_rule("active = true", input.clone()).unwrap());
+    assert!(matches_rule("count = 3", input.clone()).unwrap());
+    assert!(matches_rule("count = 3.0", input.clone()).unwrap());
+    assert!(matches_rule("ratio = 0.5", input.clone()).unwrap());
+    assert!(matches_rule("code = 42", input.clone()).unwrap());
+    assert!(matches_rule(r#"code = "42""#, input.clone()).unwrap());
+    assert!(matches_rule("note = null", input.clone()).unwrap());
+    assert!(!matches_rule("count = null", input.clone()).unwrap());
+    assert!(matches_rule("count != 4", input.clone()).unwrap());
+    assert!(matches_rule("count in [1, 3.0]", input).unwrap());
+}
+
+#[test]
+fn test_strict_comparisons() {
+    let strict = |engine: RuleEngine| engine.with_strict_comparisons(true);
+    assert!(matches_with("code = 42", serde_json::json!({"code": 42.0}), strict).unwrap());
+    assert!(matches!(
+        matches_with("code = 42", serde_json::json!({"code": "42"}), strict),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator '=' cannot be applied to string and number"
+    ));
+}
+
+#[test]
+fn test_field_to_field_comparison() {
+    let input = serde_json::json!({
+        "balance": 120,
+        "minimum_balance": 100,
+        "start_date": 20240101,
+        "end_date": 20240301,
+        "limits": {"max": 150},
+        "status": "active",
+        "active": true
+    });
+    let fields = |engine: RuleEngine| engine.with_bareword_fields(true);
+    assert!(matches_with("balance >= minimum_balance", input.clone(), fields).unwrap());
+    assert!(matches_with("end_date > start_date", input.clone(), fields).unwrap());
+    assert!(matches_rule("balance < limits.max", input.clone()).unwrap());
+    assert!(matches_with("100 <= balance", input.clone(), fields).unwrap());
+    // Bare words are strings by default, whatever fields the input has.
+    assert!(matches_rule("status = active", input.clone()).unwrap());
+    assert!(!matches_with("status = active", input.clone(), fields).unwrap());
+    assert!(matches!(
+        matches_with("status = expected_status", input.clone(), fields),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Key 'expected_status' not found"
+    ));
+    assert!(matches!(
+        matches_rule("balance < limits.min", input),
+        Err(RuleEngineError::EvaluationError(_))
+    ));
+}
+
+#[test]
+fn test_field_comparison_against_context() {
+    let rule = Rule {
+        condition: Condition::Simple("spent > budget".to_string()),
+        outcomes: vec![Outcome {
+            key: "over_budget".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]).with_bareword_fields(true);
+
+    let mut input = HashMap::new();
+    input.insert("spent".to_string(), serde_json::json!(500));
+    let mut context = HashMap::new();
+    context.insert("budget".to_string(), serde_json::json!(400));
+    assert_eq!(engine.evaluate(&input, &context).unwrap().len(), 1);
+}
+
+#[test]
+fn test_arithmetic_expressions() {
+    let input = serde_json::json!({"price": 250, "quantity": 5, "score": 60, "bonus": 85, "n": 7});
+    assert!(matches_rule("price * quantity > 1000", input.clone()).unwrap());
+    assert!(matches_rule("(score + bonus) / 2 >= 70", input.clone()).unwrap());
+    assert!(!matches_rule("score + bonus / 2 >= 120", input.clone()).unwrap());
+    assert!(matches_rule("n % 2 = 1", input.clone()).unwrap());
+    assert!(matches_rule("-n < 0", input.clone()).unwrap());
+    assert!(matches_rule("n / 2 = 3.5", input.clone()).unwrap());
+    assert!(matches_rule("price - 300 = -50", input).unwrap());
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    let input = serde_json::json!({"total": 10, "count": 0, "name": "Haile"});
+    assert!(matches!(
+        matches_rule("total / count > 1", input.clone()),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Division by zero in '10 / 0'"
+    ));
+    assert!(matches!(
+        matches_rule("name * 2 > 1", input),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator '*' cannot be applied to string and number"
+    ));
+}
+
+#[test]
+fn test_variadic_and_nested_function_calls() {
+    let rule = Rule {
+        condition: Condition::And(vec![
+            Condition::Simple("round(price, 1) = 9.9".to_string()),
+            Condition::Simple("length(trim(name)) = 5".to_string()),
+        ]),
+        outcomes: vec![Outcome {
+            key: "valid".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule])
+        .with_variadic_function("round", |args| {
+            let (value, digits) = match args {
+                [value, digits] => (value.as_f64(), digits.as_i64()),
+                _ => (None, None),
+            };
+            match (value, digits) {
+                (Some(value), Some(digits)) => {
+                    let factor = 10f64.powi(digits as i32);
+                    Ok(serde_json::json!((value * factor).round() / factor))
+                }
+                _ => Err(RuleEngineError::EvaluationError(
+                    "round expects a number and a digit count".to_string(),
+                )),
+            }
+        })
+        .with_function("trim", |value| {
+            Ok(serde_json::json!(value.as_str().unwrap_or_default().trim()))
+        })
+        .with_function("length", |value| {
+            Ok(serde_json::json!(value.as_str().unwrap_or_default().len()))
+        });
+
+    let mut input = HashMap::new();
+    input.insert("price".to_string(), serde_json::json!(9.87));
+    input.insert("name".to_string(), serde_json::json!("  Haile "));
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 1);
+}
+
+#[test]
+fn test_single_argument_function_arity() {
+    let rule = Rule {
+        condition: Condition::Simple("length(first, last) > 2".to_string()),
+        outcomes: vec![Outcome {
+            key: "valid".to_string(),
+            value: serde_json::json!(true),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(vec![rule]).with_function("length", |value| {
+        Ok(serde_json::json!(value.as_str().unwrap_or_default().len()))
+    });
+
+    let mut input = HashMap::new();
+    input.insert("first".to_string(), serde_json::json!("Haile"));
+    input.insert("last".to_string(), serde_json::json!("Selassie"));
+    let result = engine.evaluate(&input, &HashMap::new());
+    assert!(matches!(
+        result,
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Function 'length' expects 1 argument, got 2"
+    ));
+}
+
+#[test]
+fn test_builtin_functions() {
+    let input = serde_json::json!({
+        "name": "  Haile ",
+        "prices": [10, 20, 30.5],
+        "scores": [3, 9, 4],
+        "profile": {"a": 1, "b": 2},
+        "nickname": null,
+        "code": "42",
+        "delta": -7
+    });
+    assert!(matches_rule("length(trim(name)) = 5", input.clone()).unwrap());
+    assert!(matches_rule(r#"upper(trim(name)) = "HAILE""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"lower(name) contains "haile""#, input.clone()).unwrap());
+    assert!(matches_rule("sum(prices) = 60.5", input.clone()).unwrap());
+    assert!(matches_rule("round(avg(prices), 2) = 20.17", input.clone()).unwrap());
+    assert!(matches_rule("max(scores) = 9 ", input.clone()).unwrap());
+    assert!(matches_rule("min(abs(delta), 5) = 5", input.clone()).unwrap());
+    assert!(matches_rule("count(scores) = 3", input.clone()).unwrap());
+    assert!(matches_rule(r#"keys(profile) = ["a", "b"]"#, input.clone()).unwrap());
+    assert!(matches_rule(r#"coalesce(nickname, "anon") = "anon""#, input.clone()).unwrap());
+    assert!(matches_rule("to_number(code) + 1 = 43", input.clone()).unwrap());
+    assert!(matches_rule(r#"to_string(delta) = "-7""#, input).unwrap());
+}
+
+#[test]
+fn test_builtins_can_be_overridden_and_disabled() {
+    let input = serde_json::json!({"name": "Haile"});
+    let overridden =
+        |engine: RuleEngine| engine.with_function("length", |_| Ok(serde_json::json!(42)));
+    assert!(matches_with("length(name) = 42", input.clone(), overridden).unwrap());
+    assert!(matches!(
+        matches_with("length(name) = 42", input, |engine| engine.with_builtins(false)),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Unknown function 'length'"
+    ));
+}
+
+fn at_fixed_time(engine: RuleEngine) -> RuleEngine {
+    let now = chrono::DateTime::parse_from_rfc3339("2024-06-15T12:00:00Z").unwrap();
+    engine.with_clock(move || now.to_utc())
+}
+
+#[test]
+fn test_date_comparisons() {
+    let input = serde_json::json!({
+        "signup_date": "2024-03-01",
+        "last_login": "2024-06-12T08:00:00Z",
+        "created_at": "2024-05-01T12:00:00+00:00",
+        "renewed_at": 1718452800
+    });
+    assert!(matches_with("signup_date > 2024-01-01", input.clone(), at_fixed_time).unwrap());
+    assert!(
+        !matches_with(
+            "signup_date < 2024-01-01T00:00:00Z",
+            input.clone(),
+            at_fixed_time
+        )
+        .unwrap()
+    );
+    assert!(
+        matches_with(
+            "signup_date = 2024-03-01T00:00:00Z",
+            input.clone(),
+            at_fixed_time
+        )
+        .unwrap()
+    );
+    assert!(matches_with("last_login within 7d", input.clone(), at_fixed_time).unwrap());
+    assert!(!matches_with("last_login within 1d", input.clone(), at_fixed_time).unwrap());
+    assert!(matches_with("now() - created_at > 30d", input.clone(), at_fixed_time).unwrap());
+    assert!(
+        matches_with(
+            "created_at + 45d = 2024-06-15T12:00:00Z",
+            input.clone(),
+            at_fixed_time
+        )
+        .unwrap()
+    );
+    assert!(matches_with("renewed_at = now()", input.clone(), at_fixed_time).unwrap());
+    assert!(
+        matches_with(
+            "now() - signup_date >= 2w * 7",
+            input.clone(),
+            at_fixed_time
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_duration_comparisons() {
+    let input = serde_json::json!({"timeout": "90s", "elapsed": 45, "age": 30});
+    assert!(matches_with("timeout < 2m", input.clone(), at_fixed_time).unwrap());
+    assert!(matches_with("timeout = 1m30s", input.clone(), at_fixed_time).unwrap());
+    assert!(matches_with("elapsed < 1m", input.clone(), at_fixed_time).unwrap());
+    assert!(matches_with("1h30m = 90m", input.clone(), at_fixed_time).unwrap());
+    assert!(matches_with("-1m < 0s", input.clone(), at_fixed_time).unwrap());
+
+    assert!(matches!(
+        matches_with("timeout within 7d", input.clone(), at_fixed_time),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator 'within' cannot be applied to string and duration"
+    ));
+    assert!(matches!(
+        matches_with("2024-01-01 + 2024-01-02 > 1d", input, at_fixed_time),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator '+' cannot be applied to datetime and datetime"
+    ));
+}
+
+#[test]
+fn test_quantifier_conditions() {
+    let input = serde_json::json!({
+        "items": [{"sku": "A1", "price": 40}, {"sku": "B2", "price": 150}],
+        "documents": [{"verified": true}, {"verified": true}],
+        "empty": []
+    });
+    let check = |condition: Condition| {
+        matches_condition(condition, input.clone(), |engine| engine).unwrap()
+    };
+    let simple = |expr: &str| Box::new(Condition::Simple(expr.to_string()));
+
+    assert!(check(Condition::Any {
+        field: "items".to_string(),
+        binding: "item".to_string(),
+        condition: simple("item.price > 100"),
+    }));
+    assert!(!check(Condition::All {
+        field: "items".to_string(),
+        binding: "line".to_string(),
+        condition: simple("line.price > 100"),
+    }));
+    assert!(check(Condition::All {
+        field: "documents".to_string(),
+        binding: "doc".to_string(),
+        condition: simple("doc.verified = true"),
+    }));
+    assert!(check(Condition::None {
+        field: "items".to_string(),
+        binding: "item".to_string(),
+        condition: simple("item.price > 500"),
+    }));
+    assert!(!check(Condition::Any {
+        field: "empty".to_string(),
+        binding: "item".to_string(),
+        condition: simple("item = 1"),
+    }));
+    assert!(check(Condition::All {
+        field: "empty".to_string(),
+        binding: "item".to_string(),
+        condition: simple("item = 1"),
+    }));
+    // Bindings shadow input fields and nest.
+    assert!(check(Condition::Any {
+        field: "items".to_string(),
+        binding: "documents".to_string(),
+        condition: Box::new(Condition::Any {
+            field: "[1, 2, 3]".to_string(),
+            binding: "n".to_string(),
+            condition: simple("documents.price = n * 20"),
+        }),
+    }));
+}
+
+#[test]
+fn test_any_binds_matching_element_in_template() {
+    let rule = Rule {
+        condition: Condition::Or(vec![
+            Condition::Not(Box::new(Condition::Any {
+                field: "items".to_string(),
+                binding: "item".to_string(),
+                condition: Box::new(Condition::Simple("item.price > 0".to_string())),
+            })),
+            Condition::Any {
+                field: "items".to_string(),
+                binding: "item".to_string(),
+                condition: Box::new(Condition::Simple("item.price > 100".to_string())),
+            },
+        ]),
+        outcomes: vec![Outcome {
+            key: "flagged".to_string(),
+            value: serde_json::json!("{{ item.sku }} costs {{ item.price }}"),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let input: HashMap<String, serde_json::Value> = serde_json::from_value(serde_json::json!({
+        "items": [{"sku": "A1", "price": 40}, {"sku": "B2", "price": 150}]
+    }))
+    .unwrap();
+    let outcomes = RuleEngine::new(vec![rule])
+        .evaluate(&input, &HashMap::new())
+        .unwrap();
+    assert_eq!(outcomes[0].value, serde_json::json!("B2 costs 150"));
+}
+
+#[test]
+fn test_quantifier_requires_array() {
+    let condition = Condition::All {
+        field: "name".to_string(),
+        binding: "item".to_string(),
+        condition: Box::new(Condition::Simple("item = x".to_string())),
+    };
+    let input = serde_json::json!({"name": "Haile"});
+    assert!(matches!(
+        matches_condition(condition, input, |engine| engine),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Quantifier over 'name' expects an array, got \"Haile\""
+    ));
+}
+
+fn with_policy(policy: MissingKeyPolicy) -> impl FnOnce(RuleEngine) -> RuleEngine {
+    move |engine| engine.with_missing_key_policy(policy)
+}
+
+#[test]
+fn test_existence_operators() {
+    let input = serde_json::json!({
+        "name": "Haile",
+        "nickname": null,
+        "tags": [],
+        "user": {"email": ""}
+    });
+    assert!(matches_rule("name exists", input.clone()).unwrap());
+    assert!(matches_rule("nickname exists", input.clone()).unwrap());
+    assert!(!matches_rule("phone exists", input.clone()).unwrap());
+    assert!(!matches_rule("user.phone exists", input.clone()).unwrap());
+    assert!(matches_rule("nickname is_null", input.clone()).unwrap());
+    assert!(matches_rule("phone is_null", input.clone()).unwrap());
+    assert!(!matches_rule("name is_null", input.clone()).unwrap());
+    assert!(matches_rule("tags is_empty", input.clone()).unwrap());
+    assert!(matches_rule("user.email is_empty", input.clone()).unwrap());
+    assert!(matches_rule("phone is_empty", input.clone()).unwrap());
+    assert!(!matches_rule("name is_empty", input.clone()).unwrap());
+    assert!(matches_rule("(phone exists) = false", input).unwrap());
+}
+
+#[test]
+fn test_coalesce_operator() {
+    let input = serde_json::json!({"name": "Haile", "nickname": null, "score": 5});
+    assert!(matches_rule("nickname ?? name = Haile", input.clone()).unwrap());
+    assert!(matches_rule("alias ?? nickname ?? name = Haile", input.clone()).unwrap());
+    assert!(matches_rule("name ?? guest = Haile", input.clone()).unwrap());
+    // A bare fallback is a field, subject to the missing-key policy.
+    assert!(matches!(
+        matches_rule("alias ?? guest = guest", input.clone()),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Key 'guest' not found"
+    ));
+    let as_null = with_policy(MissingKeyPolicy::TreatAsNull);
+    assert!(matches_with("alias ?? guest = null", input.clone(), as_null).unwrap());
+    let as_false = with_policy(MissingKeyPolicy::ConditionFalse);
+    assert!(!matches_with("alias ?? guest != null", input.clone(), as_false).unwrap());
+    assert!(matches_rule("bonus ?? 0 + score = 5", input.clone()).unwrap());
+    assert!(matches_rule("(bonus ?? 10) + score = 15", input).unwrap());
+}
+
+#[test]
+fn test_missing_key_policies() {
+    let input = serde_json::json!({"age": 30});
+
+    assert!(matches!(
+        matches_with("nickname = Bob", input.clone(), with_policy(MissingKeyPolicy::Error)),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Key 'nickname' not found"
+    ));
+
+    assert!(
+        matches_with(
+            "nickname = null",
+            input.clone(),
+            with_policy(MissingKeyPolicy::TreatAsNull)
+        )
+        .unwrap()
+    );
+    assert!(
+        !matches_with(
+            "nickname = Bob",
+            input.clone(),
+            with_policy(MissingKeyPolicy::TreatAsNull)
+        )
+        .unwrap()
+    );
+
+    for condition in [
+        "nickname = Bob",
+        "nickname != Bob",
+        "score + 1 > 10",
+        "length(nickname) > 0",
+        "age in [30, user.limit]",
+    ] {
+        assert!(
+            !matches_with(
+                condition,
+                input.clone(),
+                with_policy(MissingKeyPolicy::ConditionFalse)
+            )
+            .unwrap(),
+            "{}",
+            condition
+        );
+    }
+
+    // A missing field only falsifies the simple condition that refers to it.
+    let condition = Condition::Or(vec![
+        Condition::Simple("nickname = Bob".to_string()),
+        Condition::Simple("age >= 18".to_string()),
+    ]);
+    assert!(
+        matches_condition(
+            condition,
+            input,
+            with_policy(MissingKeyPolicy::ConditionFalse)
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_missing_fields_as_null() {
+    let input = serde_json::json!({"name": "Haile", "nothing": null});
+    let as_null = || with_policy(MissingKeyPolicy::TreatAsNull);
+
+    // Nothing is ordered relative to null, so neither side of a range holds.
+    for condition in [
+        "age > 18",
+        "age <= 18",
+        "nothing >= 0",
+        "age between 18 and 65",
+        "signup_date < 2024-01-01",
+    ] {
+        assert!(
+            !matches_with(condition, input.clone(), as_null()).unwrap(),
+            "{}",
+            condition
+        );
+    }
+    let not_adult = Condition::Not(Box::new(Condition::Simple("age > 18".to_string())));
+    assert!(matches_condition(not_adult, input.clone(), as_null()).unwrap());
+
+    // A null collection has no elements.
+    let expensive = || Box::new(Condition::Simple("item.price > 100".to_string()));
+    let any = Condition::Any {
+        field: "items".to_string(),
+        binding: "item".to_string(),
+        condition: expensive(),
+    };
+    let all = Condition::All {
+        field: "items".to_string(),
+        binding: "item".to_string(),
+        condition: expensive(),
+    };
+    let none = Condition::None {
+        field: "nothing".to_string(),
+        binding: "item".to_string(),
+        condition: expensive(),
+    };
+    assert!(!matches_condition(any, input.clone(), as_null()).unwrap());
+    assert!(matches_condition(all, input.clone(), as_null()).unwrap());
+    assert!(matches_condition(none, input, as_null()).unwrap());
+}
+
+#[test]
+fn test_between_operator() {
+    let input = serde_json::json!({
+        "age": 65,
+        "signup_date": "2024-03-01",
+        "limits": {"low": 60, "high": 70}
+    });
+    assert!(matches_rule("age between 18 and 65", input.clone()).unwrap());
+    assert!(!matches_rule("age between [18, 65)", input.clone()).unwrap());
+    assert!(matches_rule("age between (64, 65]", input.clone()).unwrap());
+    assert!(matches_rule("age - 10 between 50 and 60", input.clone()).unwrap());
+    assert!(matches_rule("age between limits.low and limits.high", input.clone()).unwrap());
+    assert!(
+        matches_rule(
+            "signup_date between [2024-01-01, 2024-04-01)",
+            input.clone()
+        )
+        .unwrap()
+    );
+    assert!(!matches_rule("signup_date between 2024-03-02 and 2024-12-31", input).unwrap());
+}
+
+#[test]
+fn test_between_bounds_are_validated() {
+    for (condition, message) in [
+        (
+            "age between 65 and 18",
+            "Range [65, 18] has a lower bound greater than its upper bound",
+        ),
+        ("age between [18, 18)", "Range [18, 18) is empty"),
+        (
+            "age between 18 and 2024-01-01",
+            "Range [18, 2024-01-01T00:00:00+00:00] must have two numbers, dates, durations or versions as bounds",
+        ),
+    ] {
+        let rule = Rule {
+            condition: Condition::Simple(condition.to_string()),
+            ..Default::default()
+        };
+        let mut engine = RuleEngine::new(vec![]);
+        assert!(matches!(
+            engine.update_rules(vec![rule]),
+            Err(RuleEngineError::ParseError(msg)) if msg == message
+        ));
+    }
+    assert!(matches_rule("age between [18, 18]", serde_json::json!({"age": 18})).unwrap());
+}
+
+#[test]
+fn test_matches_operator() {
+    let input = serde_json::json!({
+        "email": "haile@corp.com",
+        "sku": "ABC-123",
+        "zip": 12345,
+        "pattern": "^ABC-",
+        "tags": ["a"]
+    });
+    assert!(matches_rule(r#"email matches "^[^@]+@corp\\.com$""#, input.clone()).unwrap());
+    assert!(!matches_rule(r#"email matches "@example\\.com$""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"email not matches "@example\\.com$""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"sku matches "^[A-Z]{3}-\\d+$""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"zip matches "^\\d{5}$""#, input.clone()).unwrap());
+    let fields = |engine: RuleEngine| engine.with_bareword_fields(true);
+    assert!(matches_with("sku matches pattern", input.clone(), fields).unwrap());
+    assert!(matches!(
+        matches_rule(r#"tags matches "a""#, input.clone()),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator 'matches' cannot be applied to array and string"
+    ));
+    assert!(matches!(
+        matches_with(
+            "sku matches email",
+            serde_json::json!({"sku": "A", "email": "("}),
+            fields
+        ),
+        Err(RuleEngineError::EvaluationError(msg)) if msg.starts_with("Invalid regular expression '('")
+    ));
+}
+
+#[test]
+fn test_update_rules_rejects_invalid_pattern() {
+    let rule = Rule {
+        condition: Condition::Simple(r#"id matches "[0-9""#.to_string()),
+        ..Default::default()
+    };
+    let mut engine = RuleEngine::new(vec![]);
+    assert!(matches!(
+        engine.update_rules(vec![rule]),
+        Err(RuleEngineError::ParseError(msg)) if msg.starts_with("Invalid regular expression '[0-9'")
+    ));
+}
+
+#[test]
+fn test_update_rules_rejects_invalid_quantifier_collection() {
+    let rule = Rule {
+        condition: Condition::Any {
+            field: r#"orders matches "[0-9""#.to_string(),
+            binding: "order".to_string(),
+            condition: Box::new(Condition::Simple("order.total > 100".to_string())),
+        },
+        ..Default::default()
+    };
+    let mut engine = RuleEngine::new(vec![]);
+    assert!(matches!(
+        engine.update_rules(vec![rule]),
+        Err(RuleEngineError::ParseError(msg)) if msg.starts_with("Invalid regular expression '[0-9'")
+    ));
+}
+
+#[test]
+fn test_version_comparisons() {
+    let input = serde_json::json!({"app_version": "2.10.0", "beta": "3.0.0-beta.2", "old": "v2.9"});
+    assert!(matches_rule("app_version >= 2.3.0", input.clone()).unwrap());
+    assert!(matches_rule("app_version > 2.9.0", input.clone()).unwrap());
+    assert!(matches_rule("old < 2.10.0", input.clone()).unwrap());
+    assert!(matches_rule("old = 2.9.0", input.clone()).unwrap());
+    assert!(matches_rule("beta < 3.0.0", input.clone()).unwrap());
+    assert!(matches_rule("beta > 3.0.0-beta.1", input.clone()).unwrap());
+    assert!(matches_rule("beta > 3.0.0-alpha", input.clone()).unwrap());
+    assert!(matches_rule("1.0.0+build.1 = 1.0.0+build.2", input.clone()).unwrap());
+    assert!(matches_rule("app_version between [2.0.0, 3.0.0)", input.clone()).unwrap());
+    assert!(matches!(
+        matches_rule("app_version > 2.0.0", serde_json::json!({"app_version": true})),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator '>' cannot be applied to boolean and version"
+    ));
+}
+
+#[test]
+fn test_satisfies_operator() {
+    let input =
+        serde_json::json!({"app_version": "2.10.0", "beta": "2.11.0-rc.1", "range": "~2.10"});
+    assert!(matches_rule(r#"app_version satisfies "^2.3""#, input.clone()).unwrap());
+    assert!(!matches_rule(r#"app_version satisfies "^3""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"app_version satisfies "~2.10.0""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"app_version satisfies ">=2.1, <2.11""#, input.clone()).unwrap());
+    let fields = |engine: RuleEngine| engine.with_bareword_fields(true);
+    assert!(matches_with("2.10.5 satisfies range", input.clone(), fields).unwrap());
+    // Pre-releases only satisfy requirements that opt into them.
+    assert!(!matches_rule(r#"beta satisfies "^2.3""#, input.clone()).unwrap());
+    assert!(matches_rule(r#"beta satisfies ">=2.11.0-rc.0""#, input).unwrap());
+
+    let rule = Rule {
+        condition: Condition::Simple(r#"app_version satisfies "^^2""#.to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![rule]),
+        Err(RuleEngineError::ParseError(msg)) if msg.starts_with("Invalid version requirement '^^2'")
+    ));
+}
+
+#[test]
+fn test_ip_and_cidr_matching() {
+    let input = serde_json::json!({
+        "client_ip": "10.1.2.3",
+        "v6": "2001:db8:1::1",
+        "blocked": ["203.0.113.0/24", "10.0.0.0/8"],
+    });
+    assert!(matches_rule("client_ip in 10.0.0.0/8", input.clone()).unwrap());
+    assert!(!matches_rule("client_ip in 192.168.0.0/16", input.clone()).unwrap());
+    assert!(matches_rule("client_ip not in 192.168.0.0/16", input.clone()).unwrap());
+    assert!(matches_rule("client_ip = 10.1.2.3", input.clone()).unwrap());
+    assert!(
+        matches_rule(
+            "client_ip in_cidr [192.168.0.0/16, 10.1.0.0/16]",
+            input.clone()
+        )
+        .unwrap()
+    );
+    assert!(
+        matches_rule(
+            "client_ip not in [192.168.0.0/16, 172.16.0.0/12]",
+            input.clone()
+        )
+        .unwrap()
+    );
+    assert!(matches_rule("v6 in 2001:db8::/32", input.clone()).unwrap());
+    assert!(!matches_rule("v6 in [10.0.0.0/8, fe80::/10]", input.clone()).unwrap());
+    let fields = |engine: RuleEngine| engine.with_bareword_fields(true);
+    assert!(matches_with("client_ip in_cidr blocked", input.clone(), fields).unwrap());
+    assert!(matches_rule(r#"client_ip in_cidr "10.1.0.0/16""#, input.clone()).unwrap());
+    assert!(matches!(
+        matches_rule("client_ip in 10.0.0.0/8", serde_json::json!({"client_ip": "localhost"})),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Operator 'in' cannot be applied to string and ip"
+    ));
+}
+
+#[test]
+fn test_cidr_list_with_many_prefixes() {
+    let prefixes: Vec<String> = (0..=255).map(|i| format!("100.{}.0.0/16", i)).collect();
+    let condition = format!("client_ip in [{}, 2001:db8::/32]", prefixes.join(", "));
+    let input = |ip: &str| serde_json::json!({ "client_ip": ip });
+    assert!(matches_rule(&condition, input("100.200.3.4")).unwrap());
+    assert!(matches_rule(&condition, input("2001:db8::7")).unwrap());
+    assert!(!matches_rule(&condition, input("101.0.0.1")).unwrap());
+}
+
+#[test]
+fn test_large_integers_are_exact() {
+    let input = serde_json::json!({
+        "id": 9007199254740993u64,
+        "max": u64::MAX,
+        "balance": -9007199254740993i64,
+    });
+    assert!(matches_rule("id > 9007199254740992", input.clone()).unwrap());
+    assert!(!matches_rule("id = 9007199254740992", input.clone()).unwrap());
+    assert!(matches_rule("id = \"9007199254740993\"", input.clone()).unwrap());
+    assert!(matches_rule("max = 18446744073709551615", input.clone()).unwrap());
+    assert!(matches_rule("max > 18446744073709551614", input.clone()).unwrap());
+    assert!(matches_rule("max - 1 = 18446744073709551614", input.clone()).unwrap());
+    assert!(matches_rule("id + 1 = 9007199254740994", input.clone()).unwrap());
+    let fields = |engine: RuleEngine| engine.with_bareword_fields(true);
+    assert!(matches_with("-balance = id", input.clone(), fields).unwrap());
+    assert!(matches_with("id between [9007199254740993, max]", input.clone(), fields).unwrap());
+    // Built-in functions are exact too.
+    assert!(matches_rule("max(id, 1) = 9007199254740993", input.clone()).unwrap());
+    assert!(matches_rule("sum(id, 0) = 9007199254740993", input.clone()).unwrap());
+    assert!(matches_rule("round(id) = 9007199254740993", input.clone()).unwrap());
+    assert!(matches_with("abs(balance) = id", input.clone(), fields).unwrap());
+    assert!(matches_with("avg(id, id) = id", input.clone(), fields).unwrap());
+    assert!(matches_rule("round(id, -1) = 9007199254740990", input.clone()).unwrap());
+    assert!(
+        matches_with(
+            "to_number(\"9007199254740993\") = id",
+            input.clone(),
+            fields
+        )
+        .unwrap()
+    );
+    // Mixing with a float falls back to f64.
+    assert!(matches_rule("id = 9007199254740992.0", input).unwrap());
+}
+
+#[test]
+fn test_float_arithmetic_without_decimal_mode() {
+    let input = serde_json::json!({"a": 0.1, "b": 0.2});
+    assert!(matches_rule("7 / 2 = 3.5", input.clone()).unwrap());
+    assert!(!matches_rule("a + b = 0.3", input).unwrap());
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_decimal_arithmetic() {
+    let matches_decimal = |condition: &str, input: serde_json::Value| {
+        matches_with(condition, input, |engine| {
+            engine.with_decimal_arithmetic(true)
+        })
+    };
+    let input = serde_json::json!({"a": 0.1, "b": 0.2, "price": "19.99", "quantity": 3});
+    assert!(matches_decimal("a + b = 0.3", input.clone()).unwrap());
+    assert!(matches_decimal("price * quantity = 59.97", input.clone()).unwrap());
+    assert!(matches_decimal("(a + b) * 3 <= 0.9", input.clone()).unwrap());
+    assert!(matches_decimal("1 / 3 * 3 < 1", input.clone()).unwrap());
+    assert!(matches_decimal("10 % 0.3 = 0.1", input.clone()).unwrap());
+    assert!(matches_decimal("sum(a, b) = 0.3", input.clone()).unwrap());
+    assert!(matches_decimal("avg(a, b) = 0.15", input.clone()).unwrap());
+    assert!(matches_decimal("round(2.675, 2) = 2.68", input.clone()).unwrap());
+    assert!(matches_decimal("max(a + b, 0.3) = 0.3", input).unwrap());
+}
+
+#[test]
+fn test_unresolved_reference_is_rejected() {
+    let rule = Rule {
+        condition: Condition::Ref("is_adult".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![rule]),
+        Err(RuleEngineError::ParseError(msg)) if msg.starts_with("Unresolved condition reference 'is_adult'")
+    ));
+}
+
+#[test]
+fn test_unknown_constant_is_an_error() {
+    assert!(matches!(
+        matches_rule("age >= $min_age", serde_json::json!({"age": 30})),
+        Err(RuleEngineError::EvaluationError(msg)) if msg == "Unknown constant '$min_age'"
+    ));
+}
+
+#[test]
+fn test_multiple_outcomes_in_priority_order() {
+    let outcome = |key: &str, value: serde_json::Value| Outcome {
+        key: key.to_string(),
+        value,
+        ..Default::default()
+    };
+    let rules = vec![
+        Rule {
+            condition: Condition::Simple("age > 18".to_string()),
+            outcomes: vec![outcome("eligible", serde_json::json!(true))],
+            priority: 1,
+        },
+        Rule {
+            condition: Condition::Simple("tier = gold".to_string()),
+            outcomes: vec![
+                outcome("discount", serde_json::json!(0.2)),
+                outcome("tier", serde_json::json!("gold")),
+                outcome("message", serde_json::json!("Welcome back, {{ name }}")),
+            ],
+            priority: 5,
+        },
+    ];
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(30));
+    input.insert("tier".to_string(), serde_json::json!("gold"));
+    input.insert("name".to_string(), serde_json::json!("Haile"));
+
+    let engine = RuleEngine::new(rules.clone());
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    let keys: Vec<&str> = outcomes.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, ["discount", "tier", "message", "eligible"]);
+    assert_eq!(outcomes[2].value, serde_json::json!("Welcome back, Haile"));
+
+    // Stopping at the first match still emits all of its outcomes.
+    let engine = RuleEngine::new(rules).with_stop_on_first_match(true);
+    assert_eq!(engine.evaluate(&input, &HashMap::new()).unwrap().len(), 3);
+}
+
+#[test]
+fn test_evaluate_resolved_strategies() {
+    let rule = |condition: &str, priority: i32, outcomes: Vec<(&str, serde_json::Value)>| Rule {
+        condition: Condition::Simple(condition.to_string()),
+        outcomes: outcomes
+            .into_iter()
+            .map(|(key, value)| Outcome {
+                key: key.to_string(),
+                value,
+                ..Default::default()
+            })
+            .collect(),
+        priority,
+    };
+    let rules = vec![
+        rule(
+            "age > 18",
+            1,
+            vec![
+                ("tier", serde_json::json!("silver")),
+                ("discount", serde_json::json!(5)),
+                ("tags", serde_json::json!("adult")),
+                ("limits", serde_json::json!({"daily": 100, "monthly": 2000})),
+            ],
+        ),
+        rule(
+            "vip = true",
+            10,
+            vec![
+                ("tier", serde_json::json!("gold")),
+                ("discount", serde_json::json!(15)),
+                ("tags", serde_json::json!("vip")),
+                ("limits", serde_json::json!({"daily": 500})),
+            ],
+        ),
+        rule("age > 65", 1, vec![("tier", serde_json::json!("senior"))]),
+    ];
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(30));
+    input.insert("vip".to_string(), serde_json::json!(true));
+    let context = HashMap::new();
+
+    let resolved = RuleEngine::new(rules.clone())
+        .evaluate_resolved(&input, &context)
+        .unwrap();
+    assert_eq!(resolved.len(), 4);
+    assert_eq!(resolved["tier"], serde_json::json!("gold"));
+    assert_eq!(resolved["discount"], serde_json::json!(15));
+
+    let resolve = |strategy| {
+        RuleEngine::new(rules.clone())
+            .with_resolution_strategy(strategy)
+            .evaluate_resolved(&input, &context)
+    };
+    assert_eq!(
+        resolve(ResolutionStrategy::First).unwrap()["tier"],
+        "silver"
+    );
+    assert_eq!(resolve(ResolutionStrategy::Last).unwrap()["tier"], "gold");
+    assert_eq!(
+        resolve(ResolutionStrategy::Collect).unwrap()["tags"],
+        serde_json::json!(["vip", "adult"])
+    );
+    assert!(matches!(
+        resolve(ResolutionStrategy::Sum),
+        Err(RuleEngineError::EvaluationError(msg)) if msg.starts_with("Cannot resolve outcome '")
+    ));
+
+    let resolved = RuleEngine::new(rules)
+        .with_resolution_strategy(ResolutionStrategy::Collect)
+        .with_key_resolution_strategy("discount", ResolutionStrategy::Sum)
+        .with_key_resolution_strategy("limits", ResolutionStrategy::DeepMerge)
+        .evaluate_resolved(&input, &context)
+        .unwrap();
+    assert_eq!(resolved["discount"], serde_json::json!(20));
+    assert_eq!(resolved["tier"], serde_json::json!(["gold", "silver"]));
+    assert_eq!(
+        resolved["limits"],
+        serde_json::json!({"daily": 500, "monthly": 2000})
+    );
+}
+
+#[test]
+fn test_nested_outcome_templates() {
+    let rule = Rule {
+        condition: Condition::Simple("age > 18".to_string()),
+        outcomes: vec![Outcome {
+            key: "notice".to_string(),
+            value: serde_json::json!({
+                "message": "Hi {{ name }}",
+                "tags": ["{{ tier }}", "adult", 18],
+                "meta": {"{{ tier }}_since": "{{ year }}", "raw": "{ not a template }"},
+            }),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(30));
+    input.insert("name".to_string(), serde_json::json!("Haile"));
+    input.insert("tier".to_string(), serde_json::json!("gold"));
+    let mut context = HashMap::new();
+    context.insert("year".to_string(), serde_json::json!(2024));
+
+    let outcomes = RuleEngine::new(vec![rule.clone()])
+        .evaluate(&input, &context)
+        .unwrap();
+    assert_eq!(
+        outcomes[0].value,
+        serde_json::json!({
+            "message": "Hi Haile",
+            "tags": ["gold", "adult", 18],
+            "meta": {"{{ tier }}_since": "2024", "raw": "{ not a template }"},
+        })
+    );
+
+    let outcomes = RuleEngine::new(vec![rule.clone()])
+        .with_templated_keys(true)
+        .evaluate(&input, &context)
+        .unwrap();
+    assert_eq!(
+        outcomes[0].value["meta"],
+        serde_json::json!({"gold_since": "2024", "raw": "{ not a template }"})
+    );
+
+    let colliding = Rule {
+        outcomes: vec![Outcome {
+            key: "notice".to_string(),
+            value: serde_json::json!({"{{ tier }}": 1, "gold": 2}),
+            ..Default::default()
+        }],
+        ..rule.clone()
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![colliding])
+            .with_templated_keys(true)
+            .evaluate(&input, &context),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Outcome 'notice' renders the object key 'gold' more than once"
+    ));
+
+    let invalid = Rule {
+        outcomes: vec![Outcome {
+            key: "notice".to_string(),
+            value: serde_json::json!({"tags": ["{{ unclosed"]}),
+            ..Default::default()
+        }],
+        ..rule
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![invalid]),
+        Err(RuleEngineError::ParseError(msg)) if msg.starts_with("Invalid template '{{ unclosed'")
+    ));
+}
+
+#[test]
+fn test_typed_template_outcomes() {
+    let outcome = |key: &str, value: serde_json::Value, value_type| Outcome {
+        key: key.to_string(),
+        value,
+        value_type: Some(value_type),
+        ..Default::default()
+    };
+    let rule = Rule {
+        condition: Condition::Simple("amount > 0".to_string()),
+        outcomes: vec![
+            outcome(
+                "discounted",
+                serde_json::json!("{{ amount * 0.5 }}"),
+                OutcomeType::Number,
+            ),
+            outcome(
+                "large",
+                serde_json::json!("{{ amount > 100 }}"),
+                OutcomeType::Bool,
+            ),
+            outcome(
+                "limits",
+                serde_json::json!({"{{ tier }}": "[{{ amount }}, 2]"}),
+                OutcomeType::Json,
+            ),
+            outcome(
+                "label",
+                serde_json::json!("{{ amount }}"),
+                OutcomeType::String,
+            ),
+        ],
+        ..Default::default()
+    };
+    let mut input = HashMap::new();
+    input.insert("amount".to_string(), serde_json::json!(250));
+    input.insert("tier".to_string(), serde_json::json!("gold"));
+
+    let outcomes = RuleEngine::new(vec![rule.clone()])
+        .with_templated_keys(true)
+        .evaluate(&input, &HashMap::new())
+        .unwrap();
+    assert_eq!(outcomes[0].value, serde_json::json!(125));
+    assert_eq!(outcomes[1].value, serde_json::json!(true));
+    assert_eq!(outcomes[2].value, serde_json::json!({"gold": [250, 2]}));
+    assert_eq!(outcomes[3].value, serde_json::json!("250"));
+
+    input.insert("tier".to_string(), serde_json::json!("silver"));
+    let invalid = Rule {
+        outcomes: vec![outcome(
+            "discount",
+            serde_json::json!("{{ tier }}"),
+            OutcomeType::Number,
+        )],
+        ..rule.clone()
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![invalid]).evaluate(&input, &HashMap::new()),
+        Err(RuleEngineError::EvaluationError(msg))
+            if msg == "Outcome 'discount' rendered 'silver', which is not a valid number"
+    ));
+
+    let untemplated = Rule {
+        outcomes: vec![outcome(
+            "discount",
+            serde_json::json!("90"),
+            OutcomeType::Number,
+        )],
+        ..rule
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![untemplated]),
+        Err(RuleEngineError::ParseError(msg))
+            if msg == "Outcome 'discount' has a type but its value holds no template"
+    ));
+}
+
+#[test]
+fn test_expression_outcomes() {
+    let outcome = |key: &str, expr: &str| Outcome {
+        key: key.to_string(),
+        expr: Some(expr.to_string()),
+        ..Default::default()
+    };
+    let rule = Rule {
+        condition: Condition::Any {
+            field: "items".to_string(),
+            binding: "item".to_string(),
+            condition: Box::new(Condition::Simple("item.price > 100".to_string())),
+        },
+        outcomes: vec![
+            outcome("total", "price * quantity * (1 - discount)"),
+            outcome("bulk", "quantity >= 10"),
+            outcome("expensive_sku", "item.sku"),
+            outcome("label", "shout(name)"),
+            outcome("nickname", "nickname ?? name"),
+        ],
+        ..Default::default()
+    };
+    let input: HashMap<String, serde_json::Value> = serde_json::from_value(serde_json::json!({
+        "price": 20,
+        "quantity": 5,
+        "discount": 0.5,
+        "name": "Haile",
+        "items": [{"sku": "A1", "price": 40}, {"sku": "B2", "price": 150}]
+    }))
+    .unwrap();
+
+    let engine = RuleEngine::new(vec![rule.clone()]).with_function("shout", |value| {
+        Ok(serde_json::json!(format!(
+            "{}!",
+            value.as_str().unwrap_or_default()
+        )))
+    });
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    let values: Vec<_> = outcomes.iter().map(|o| o.value.clone()).collect();
+    assert_eq!(
+        values,
+        [
+            serde_json::json!(50.0),
+            serde_json::json!(false),
+            serde_json::json!("B2"),
+            serde_json::json!("Haile!"),
+            serde_json::json!("Haile"),
+        ]
+    );
+
+    let invalid = |outcome: Outcome| Rule {
+        outcomes: vec![outcome],
+        ..rule.clone()
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![invalid(outcome("total", "price *"))]),
+        Err(RuleEngineError::ParseError(_))
+    ));
+    let both = Outcome {
+        value: serde_json::json!(1),
+        ..outcome("total", "price")
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![invalid(both)]),
+        Err(RuleEngineError::ParseError(msg)) if msg == "Outcome 'total' has both a value and an expr"
+    ));
+    let typed = Outcome {
+        value_type: Some(OutcomeType::Bool),
+        ..outcome("total", "price + 1")
+    };
+    assert!(matches!(
+        RuleEngine::new(vec![]).update_rules(vec![invalid(typed)]),
+        Err(RuleEngineError::ParseError(msg)) if msg == "Outcome 'total' has both a type and an expr"
+    ));
+}
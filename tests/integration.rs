@@ -45,7 +45,120 @@ fn test_dynamic_rule_update() {
     ]
     "#;
     let new_rules = dynarule::parser::parse_rules(new_json).unwrap();
-    engine.update_rules(new_rules);
+    engine.update_rules(new_rules).unwrap();
     let outcomes = engine.evaluate(&input, &context).unwrap();
     assert_eq!(outcomes.len(), 0); // Age 25 no longer matches
 }
+
+#[test]
+fn test_update_rules_rejects_invalid_rules() {
+    let rules = dynarule::parser::parse_rules(
+        r#"[{"condition": {"type": "Simple", "value": "age > 18"}, "outcome": {"key": "eligible", "value": true}}]"#,
+    )
+    .unwrap();
+    let mut engine = RuleEngine::new(rules);
+
+    let invalid = r#"[{"condition": {"type": "Simple", "value": "age >"}, "outcome": {"key": "senior", "value": true}}]"#;
+    assert!(matches!(
+        dynarule::parser::parse_rules(invalid),
+        Err(dynarule::RuleEngineError::ParseError(_))
+    ));
+    let invalid: Vec<dynarule::Rule> = serde_json::from_str(invalid).unwrap();
+    let result = engine.update_rules(invalid);
+    assert!(matches!(
+        result,
+        Err(dynarule::RuleEngineError::ParseError(_))
+    ));
+
+    // The previous rules stay in place.
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(25));
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].key, "eligible");
+    assert_eq!(engine.rules().len(), 1);
+}
+
+#[test]
+fn test_rule_set_constants() {
+    let json = r#"
+    {
+        "constants": {"min_age": 18, "large_order": 1000},
+        "rules": [
+            {
+                "condition": {"type": "Simple", "value": "age >= $min_age"},
+                "outcome": {"key": "eligible", "value": "at least {{ constants.min_age }}"}
+            },
+            {
+                "condition": {"type": "Simple", "value": "total > $large_order"},
+                "outcome": {"key": "review", "value": true}
+            }
+        ]
+    }
+    "#;
+    let rule_set = dynarule::parser::parse_rule_set(json).unwrap();
+    assert_eq!(rule_set.constants["min_age"], serde_json::json!(18));
+    let mut input = HashMap::new();
+    input.insert("age".to_string(), serde_json::json!(19));
+    input.insert("total".to_string(), serde_json::json!(1500));
+
+    let engine = RuleEngine::from_rule_set(rule_set.clone());
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].value, serde_json::json!("at least 18"));
+
+    // Overrides win over the document, also after the rule set is replaced.
+    let mut overrides = HashMap::new();
+    overrides.insert("min_age".to_string(), serde_json::json!(21));
+    let mut engine = RuleEngine::from_rule_set(rule_set.clone()).with_constants(overrides);
+    let outcomes = engine.evaluate(&input, &HashMap::new()).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].key, "review");
+
+    let mut rule_set = rule_set;
+    rule_set
+        .constants
+        .insert("large_order".to_string(), serde_json::json!(2000));
+    engine.update_rule_set(rule_set).unwrap();
+    assert!(engine.evaluate(&input, &HashMap::new()).unwrap().is_empty());
+}
+
+#[test]
+fn test_rules_only_apis_reject_constants() {
+    let json = r#"
+    {
+        "constants": {"min_age": 18},
+        "rules": [
+            {
+                "condition": {"type": "Simple", "value": "age >= $min_age"},
+                "outcome": {"key": "eligible", "value": true}
+            }
+        ]
+    }
+    "#;
+    let expected = "The rules declare constants (min_age); load them as a rule set to keep them";
+    assert!(matches!(
+        dynarule::parser::parse_rules(json),
+        Err(dynarule::RuleEngineError::ParseError(msg)) if msg == expected
+    ));
+
+    let path = std::env::temp_dir().join(format!("dynarule-constants-{}.json", std::process::id()));
+    std::fs::write(&path, json).unwrap();
+    let loaded = dynarule::Config::load_from_file(path.to_str().unwrap());
+    let rule_set = dynarule::Config::load_rule_set_from_file(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        loaded,
+        Err(dynarule::RuleEngineError::ParseError(msg)) if msg == expected
+    ));
+    assert_eq!(
+        rule_set.unwrap().constants["min_age"],
+        serde_json::json!(18)
+    );
+
+    // Without constants the document form is still accepted.
+    let without = json
+        .replace(r#""constants": {"min_age": 18},"#, "")
+        .replace("$min_age", "18");
+    assert_eq!(dynarule::parser::parse_rules(&without).unwrap().len(), 1);
+}
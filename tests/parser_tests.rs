@@ -1,4 +1,4 @@
-use dynarule::{Condition, parser};
+use dynarule::{Condition, OutcomeType, parser};
 
 #[test]
 fn test_parse_valid_json() {
@@ -12,8 +12,8 @@ fn test_parse_valid_json() {
     if let dynarule::Condition::Simple(expr) = &rules[0].condition {
         assert_eq!(expr, "age > 18");
     }
-    assert_eq!(rules[0].outcome.key, "eligible");
-    assert_eq!(rules[0].outcome.value, serde_json::json!(true));
+    assert_eq!(rules[0].outcomes[0].key, "eligible");
+    assert_eq!(rules[0].outcomes[0].value, serde_json::json!(true));
 }
 
 #[test]
@@ -50,5 +50,353 @@ fn test_parse_nested_json() {
             assert_eq!(expr, "age > 18");
         }
     }
-    assert_eq!(rules[0].outcome.key, "access");
+    assert_eq!(rules[0].outcomes[0].key, "access");
+}
+
+#[test]
+fn test_parse_combinators_json() {
+    let json = r#"
+    [
+        {
+            "condition": {
+                "type": "AtLeast",
+                "value": {
+                    "n": 2,
+                    "conditions": [
+                        {"type": "Not", "value": {"type": "Simple", "value": "status = banned"}},
+                        {"type": "Xor", "value": [
+                            {"type": "Simple", "value": "age > 18"},
+                            {"type": "Simple", "value": "guardian = true"}
+                        ]},
+                        {"type": "AtMost", "value": {"n": 0, "conditions": []}}
+                    ]
+                }
+            },
+            "outcome": {"key": "access", "value": "granted"}
+        }
+    ]
+    "#;
+    let rules = parser::parse_rules(json).unwrap();
+    match &rules[0].condition {
+        Condition::AtLeast { n, conditions } => {
+            assert_eq!(*n, 2);
+            assert!(
+                matches!(&conditions[0], Condition::Not(inner) if matches!(**inner, Condition::Simple(_)))
+            );
+            assert!(matches!(&conditions[1], Condition::Xor(conds) if conds.len() == 2));
+            assert!(matches!(&conditions[2], Condition::AtMost { n: 0, .. }));
+        }
+        other => panic!("unexpected condition {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_quantifiers_json() {
+    let json = r#"
+    [
+        {
+            "condition": {
+                "type": "Any",
+                "value": {
+                    "field": "order.items",
+                    "as": "line",
+                    "condition": {"type": "Simple", "value": "line.price > 100"}
+                }
+            },
+            "outcome": {"key": "big_ticket", "value": "{{ line.sku }}"}
+        },
+        {
+            "condition": {
+                "type": "None",
+                "value": {
+                    "field": "documents",
+                    "condition": {"type": "Simple", "value": "item.verified = false"}
+                }
+            },
+            "outcome": {"key": "verified", "value": true}
+        }
+    ]
+    "#;
+    let rules = parser::parse_rules(json).unwrap();
+    assert!(matches!(
+        &rules[0].condition,
+        Condition::Any { field, binding, .. } if field == "order.items" && binding == "line"
+    ));
+    assert!(matches!(
+        &rules[1].condition,
+        Condition::None { binding, .. } if binding == "item"
+    ));
+}
+
+#[test]
+fn test_parse_rules_rejects_invalid_patterns() {
+    let json = r#"
+    [
+        {
+            "condition": {"type": "Or", "value": [
+                {"type": "Simple", "value": "sku matches \"^[A-Z]{3}-\\\\d+$\""},
+                {"type": "Simple", "value": "email matches \"^(unclosed\""}
+            ]},
+            "outcome": {"key": "valid", "value": true}
+        }
+    ]
+    "#;
+    let err = parser::parse_rules(json).unwrap_err();
+    assert!(matches!(
+        err,
+        dynarule::RuleEngineError::ParseError(msg)
+            if msg.starts_with("Invalid regular expression '^(unclosed'")
+    ));
+
+    let valid = json.replace("^(unclosed", r"^[^@]+@corp\\\\.com$");
+    assert_eq!(parser::parse_rules(&valid).unwrap().len(), 1);
+}
+
+#[test]
+fn test_parse_rules_checks_quantifier_collections() {
+    let json = r#"
+    [
+        {
+            "condition": {"type": "Any", "value": {
+                "field": "orders between [10, 1]",
+                "as": "order",
+                "condition": {"type": "Simple", "value": "order.total > 100"}
+            }},
+            "outcome": {"key": "flagged", "value": true}
+        }
+    ]
+    "#;
+    assert!(matches!(
+        parser::parse_rules(json),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg == "Range [10, 1] has a lower bound greater than its upper bound"
+    ));
+}
+
+#[test]
+fn test_parse_rules_rejects_syntax_errors() {
+    for condition in ["age >", "signup_date > 2024-02-30", "a = (1"] {
+        let json = format!(
+            r#"[{{"condition": {{"type": "Not", "value": {{"type": "Simple", "value": "{}"}}}},
+                "outcome": {{"key": "k", "value": true}}}}]"#,
+            condition
+        );
+        assert!(
+            matches!(
+                parser::parse_rules(&json),
+                Err(dynarule::RuleEngineError::ParseError(msg)) if msg.ends_with(&format!("in '{}'", condition))
+            ),
+            "{}",
+            condition
+        );
+    }
+}
+
+#[test]
+fn test_parse_rules_resolves_named_conditions() {
+    let json = r#"
+    {
+        "conditions": {
+            "is_adult": {"type": "Simple", "value": "age >= 18"},
+            "is_verified_adult": {"type": "And", "value": [
+                {"type": "Ref", "value": "is_adult"},
+                {"type": "Simple", "value": "verified = true"}
+            ]}
+        },
+        "rules": [
+            {
+                "condition": {"type": "Not", "value": {"type": "Ref", "value": "is_verified_adult"}},
+                "outcome": {"key": "blocked", "value": true}
+            }
+        ]
+    }
+    "#;
+    let rules = parser::parse_rules(json).unwrap();
+    match &rules[0].condition {
+        Condition::Not(inner) => match inner.as_ref() {
+            Condition::And(conditions) => {
+                assert!(matches!(&conditions[0], Condition::Simple(expr) if expr == "age >= 18"));
+                assert!(matches!(&conditions[1], Condition::Simple(_)));
+            }
+            other => panic!("unexpected condition {:?}", other),
+        },
+        other => panic!("unexpected condition {:?}", other),
+    }
+
+    let engine = dynarule::RuleEngine::new(rules);
+    let input: std::collections::HashMap<String, serde_json::Value> =
+        serde_json::from_value(serde_json::json!({"age": 30, "verified": false})).unwrap();
+    let outcomes = engine
+        .evaluate(&input, &std::collections::HashMap::new())
+        .unwrap();
+    assert_eq!(outcomes.len(), 1);
+}
+
+#[test]
+fn test_parse_rules_rejects_bad_references() {
+    let rules = |conditions: &str, condition: &str| {
+        format!(
+            r#"{{"conditions": {}, "rules": [{{"condition": {}, "outcome": {{"key": "k", "value": 1}}}}]}}"#,
+            conditions, condition
+        )
+    };
+    let error = |json: String| match parser::parse_rules(&json) {
+        Err(dynarule::RuleEngineError::ParseError(msg)) => msg,
+        other => panic!("expected a parse error, got {:?}", other),
+    };
+
+    assert_eq!(
+        error(rules("{}", r#"{"type": "Ref", "value": "missing"}"#)),
+        "Unknown condition reference 'missing'"
+    );
+    // Definitions are checked even when no rule uses them.
+    assert_eq!(
+        error(rules(
+            r#"{"a": {"type": "Or", "value": [{"type": "Ref", "value": "b"}]},
+                "b": {"type": "Not", "value": {"type": "Ref", "value": "a"}}}"#,
+            r#"{"type": "Simple", "value": "age > 18"}"#
+        )),
+        "Condition reference cycle: a -> b -> a"
+    );
+    assert_eq!(
+        error(rules(
+            r#"{"self": {"type": "Ref", "value": "self"}}"#,
+            r#"{"type": "Ref", "value": "self"}"#
+        )),
+        "Condition reference cycle: self -> self"
+    );
+    // A plain array of rules has no named conditions to refer to.
+    let json =
+        r#"[{"condition": {"type": "Ref", "value": "x"}, "outcome": {"key": "k", "value": 1}}]"#;
+    assert_eq!(error(json.to_string()), "Unknown condition reference 'x'");
+}
+
+#[test]
+fn test_parse_single_and_multiple_outcomes() {
+    let json = r#"
+    [
+        {
+            "condition": {"type": "Simple", "value": "tier = gold"},
+            "outcomes": [
+                {"key": "discount", "value": 0.2},
+                {"key": "tier", "value": "gold"},
+                {"key": "message", "value": "Welcome back, {{ name }}"}
+            ]
+        },
+        {"condition": {"type": "Simple", "value": "age > 18"}, "outcome": {"key": "eligible", "value": true}}
+    ]
+    "#;
+    let rules = parser::parse_rules(json).unwrap();
+    let keys: Vec<&str> = rules[0].outcomes.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, ["discount", "tier", "message"]);
+    assert_eq!(rules[1].outcomes.len(), 1);
+    assert_eq!(rules[1].outcomes[0].key, "eligible");
+
+    let serialized = serde_json::to_value(&rules[1]).unwrap();
+    assert_eq!(serialized["outcomes"][0]["key"], "eligible");
+    let both = r#"[{"condition": {"type": "Simple", "value": "a = 1"},
+        "outcome": {"key": "k", "value": 1}, "outcomes": []}]"#;
+    assert!(parser::parse_rules(both).is_err());
+
+    // Errors within an outcome are reported as they are.
+    for (outcome, message) in [
+        (
+            r#""outcome": {"key": 5, "value": true}"#,
+            "invalid type: integer `5`, expected a string",
+        ),
+        (
+            r#""outcomes": [{"key": "k", "value": 1}, {"value": 2}]"#,
+            "missing field `key`",
+        ),
+    ] {
+        let json = format!(
+            r#"[{{"condition": {{"type": "Simple", "value": "a = 1"}}, {}}}]"#,
+            outcome
+        );
+        assert!(
+            matches!(
+                parser::parse_rules(&json),
+                Err(dynarule::RuleEngineError::ParseError(msg)) if msg.contains(message)
+            ),
+            "{}",
+            outcome
+        );
+    }
+}
+
+#[test]
+fn test_parse_outcome_type() {
+    let json = r#"[{"condition": {"type": "Simple", "value": "a = 1"},
+        "outcomes": [{"key": "total", "value": "{{ a * 2 }}", "type": "number"},
+                     {"key": "label", "value": "{{ a }}"}]}]"#;
+    let rules = parser::parse_rules(json).unwrap();
+    assert_eq!(rules[0].outcomes[0].value_type, Some(OutcomeType::Number));
+    assert_eq!(rules[0].outcomes[1].value_type, None);
+    let serialized = serde_json::to_value(&rules[0].outcomes[1]).unwrap();
+    assert!(serialized.get("type").is_none());
+    let unknown = json.replace("\"number\"", "\"date\"");
+    assert!(parser::parse_rules(&unknown).is_err());
+
+    let literal = json.replace("{{ a * 2 }}", "5");
+    assert!(matches!(
+        parser::parse_rules(&literal),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg == "Outcome 'total' has a type but its value holds no template"
+    ));
+    let unterminated = json.replace("{{ a }}", "{{ oops ");
+    assert!(matches!(
+        parser::parse_rules(&unterminated),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg.starts_with("Invalid template '{{ oops '")
+    ));
+}
+
+#[test]
+fn test_parse_expression_outcome() {
+    let json = r#"[{"condition": {"type": "Simple", "value": "quantity > 0"},
+        "outcome": {"key": "total", "expr": "price * quantity"}}]"#;
+    let rules = parser::parse_rules(json).unwrap();
+    assert_eq!(
+        rules[0].outcomes[0].expr.as_deref(),
+        Some("price * quantity")
+    );
+    assert!(rules[0].outcomes[0].value.is_null());
+
+    let invalid = json.replace("price * quantity", "price +");
+    assert!(matches!(
+        parser::parse_rules(&invalid),
+        Err(dynarule::RuleEngineError::ParseError(msg)) if msg.ends_with("in 'price +'")
+    ));
+    let typed = json.replace(r#""expr""#, r#""type": "number", "expr""#);
+    assert!(matches!(
+        parser::parse_rules(&typed),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg == "Outcome 'total' has both a type and an expr"
+    ));
+}
+
+#[test]
+fn test_parse_rules_rejects_invalid_cidr_literals() {
+    let rules = |condition: &str| {
+        parser::parse_rules(&format!(
+            r#"[{{"condition": {{"type": "Simple", "value": "{}"}},
+                "outcome": {{"key": "internal", "value": true}}}}]"#,
+            condition
+        ))
+    };
+    assert!(matches!(
+        rules("client_ip in 10.0.0.5/8"),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg.contains("Invalid CIDR literal '10.0.0.5/8': host bits are set")
+    ));
+    assert!(matches!(
+        rules("client_ip = 300.1.1.1"),
+        Err(dynarule::RuleEngineError::ParseError(msg))
+            if msg.contains("Invalid IP address literal '300.1.1.1'")
+    ));
+    assert!(matches!(
+        rules("client_ip in [10.0.0.0/8, 192.168.0.0/33]"),
+        Err(dynarule::RuleEngineError::ParseError(_))
+    ));
+    assert!(rules("client_ip in [10.0.0.0/8, 2001:db8::/32]").is_ok());
 }